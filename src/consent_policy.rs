@@ -0,0 +1,138 @@
+use crate::event_fields::consent_name;
+use crate::exports::edgee::components::data_collection::{Consent, Data, Event};
+use crate::routing::Destination;
+use crate::s3_key::check_key_prefix;
+use crate::s3_payload::{check_bucket_name, get_optional_setting, get_setting};
+use std::collections::HashMap;
+
+/// What to do with events whose consent is denied or pending.
+/// Events with granted or unknown consent are always sent as is.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ConsentPolicy {
+    /// Consent is ignored.
+    #[default]
+    None,
+    /// Events aren't sent.
+    Drop,
+    /// IP, user IDs and user agent are removed before serializing.
+    Anonymize,
+    /// Events go to another bucket and/or prefix.
+    Route {
+        denied: Destination,
+        pending: Destination,
+    },
+}
+
+/// Bucket and key prefix overrides of a consent state, for routed events.
+fn consent_destination(
+    settings_map: &HashMap<String, String>,
+    consent: &str,
+) -> anyhow::Result<Destination> {
+    let bucket_key = format!("consent_{consent}_s3_bucket");
+    let key_prefix_key = format!("consent_{consent}_s3_key_prefix");
+    let destination = Destination {
+        bucket: get_optional_setting(settings_map, &bucket_key),
+        key_prefix: get_optional_setting(settings_map, &key_prefix_key),
+        ..Destination::default()
+    };
+    if let Some(bucket) = &destination.bucket {
+        check_bucket_name(settings_map, &bucket_key, bucket)?;
+    }
+    if let Some(key_prefix) = &destination.key_prefix {
+        check_key_prefix(&key_prefix_key, key_prefix)?;
+    }
+    Ok(destination)
+}
+
+impl ConsentPolicy {
+    pub fn new(settings_map: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let denied = consent_destination(settings_map, "denied")?;
+        let pending = consent_destination(settings_map, "pending")?;
+
+        let policy = match get_setting(settings_map, "consent_policy") {
+            "" | "none" => Self::None,
+            "drop" => Self::Drop,
+            "anonymize" => Self::Anonymize,
+            "route" => {
+                if denied.is_empty() && pending.is_empty() {
+                    anyhow::bail!(
+                        "Consent policy route requires a bucket or key prefix for denied or pending consent"
+                    );
+                }
+                return Ok(Self::Route { denied, pending });
+            }
+            other => anyhow::bail!(
+                "Invalid consent policy: {other} (expected none, drop, anonymize or route)"
+            ),
+        };
+
+        if !denied.is_empty() || !pending.is_empty() {
+            anyhow::bail!("Consent destinations require consent_policy = route");
+        }
+        Ok(policy)
+    }
+
+    /// Applies the policy to the event, before it's serialized.
+    /// Returns an error for dropped events, and the destination to use for routed events.
+    pub fn apply(&self, edgee_event: &mut Event) -> anyhow::Result<Option<&Destination>> {
+        let consent = edgee_event.consent;
+        if !matches!(consent, Some(Consent::Denied) | Some(Consent::Pending)) {
+            return Ok(None);
+        }
+
+        match self {
+            Self::None => Ok(None),
+            Self::Drop => anyhow::bail!(
+                "Event dropped by the consent policy (consent is {})",
+                consent_name(consent.as_ref())
+            ),
+            Self::Anonymize => {
+                anonymize(edgee_event);
+                Ok(None)
+            }
+            Self::Route { denied, pending } => {
+                let destination = match consent {
+                    Some(Consent::Denied) => denied,
+                    _ => pending,
+                };
+                Ok(Some(destination).filter(|destination| !destination.is_empty()))
+            }
+        }
+    }
+}
+
+/// Removes the IP, user IDs and user agent of the event.
+fn anonymize(edgee_event: &mut Event) {
+    let client = &mut edgee_event.context.client;
+    for value in [
+        &mut client.ip,
+        &mut client.user_agent,
+        &mut client.user_agent_architecture,
+        &mut client.user_agent_bitness,
+        &mut client.user_agent_full_version_list,
+        &mut client.user_agent_version_list,
+        &mut client.user_agent_mobile,
+        &mut client.user_agent_model,
+    ] {
+        value.clear();
+    }
+
+    let user = &mut edgee_event.context.user;
+    for value in [
+        &mut user.user_id,
+        &mut user.anonymous_id,
+        &mut user.edgee_id,
+    ] {
+        value.clear();
+    }
+
+    if let Data::User(user) = &mut edgee_event.data {
+        for value in [
+            &mut user.user_id,
+            &mut user.anonymous_id,
+            &mut user.edgee_id,
+        ] {
+            value.clear();
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::exports::edgee::components::data_collection::Event;
+use crate::routing::Destination;
+use crate::s3_payload::{check_bucket_name, get_setting, split_list};
+use std::collections::HashMap;
+
+/// Continent names and codes, as `context.client.continent` may hold either of them.
+const CONTINENTS: &[(&str, &str)] = &[
+    ("Africa", "AF"),
+    ("Antarctica", "AN"),
+    ("Asia", "AS"),
+    ("Europe", "EU"),
+    ("North America", "NA"),
+    ("Oceania", "OC"),
+    ("South America", "SA"),
+];
+
+/// Name of a continent given by its name or its code.
+fn continent_name(continent: &str) -> Option<&'static str> {
+    CONTINENTS
+        .iter()
+        .find(|(name, code)| {
+            name.eq_ignore_ascii_case(continent) || code.eq_ignore_ascii_case(continent)
+        })
+        .map(|(name, _)| *name)
+}
+
+/// Region and bucket of each event, picked from the client's country or continent.
+/// Events from other locations keep the default region and bucket.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DataResidency {
+    /// Destinations per country code (e.g. `CH`), checked first.
+    pub countries: Vec<(String, Destination)>,
+    /// Destinations per continent name (e.g. `Europe`).
+    pub continents: Vec<(String, Destination)>,
+}
+
+impl DataResidency {
+    /// Parses `data_residency`: comma-separated `location=region/bucket` entries,
+    /// where the location is a continent name or a two-letter country code.
+    pub fn new(settings_map: &HashMap<String, String>) -> anyhow::Result<Option<Self>> {
+        let entries = get_setting(settings_map, "data_residency");
+
+        let mut residency = Self::default();
+        for entry in split_list(entries) {
+            let Some((location, destination)) = entry.split_once('=') else {
+                anyhow::bail!("Invalid data residency: {entry} (expected location=region/bucket)");
+            };
+            let (location, destination) = (location.trim(), destination.trim());
+            let Some((region, bucket)) = destination.split_once('/') else {
+                anyhow::bail!("Invalid data residency: {entry} (expected location=region/bucket)");
+            };
+            let (region, bucket) = (region.trim(), bucket.trim());
+            if region.is_empty()
+                || !region
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            {
+                anyhow::bail!("Invalid data residency region for {location}: {region}");
+            }
+            if bucket.is_empty() {
+                anyhow::bail!("Missing data residency bucket for {location}");
+            }
+            check_bucket_name(settings_map, "data_residency", bucket)?;
+            let destination = Destination {
+                bucket: Some(bucket.to_string()),
+                region: Some(region.to_string()),
+                ..Destination::default()
+            };
+
+            let locations = if let Some((continent, _)) = CONTINENTS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(location))
+            {
+                residency
+                    .continents
+                    .push((continent.to_string(), destination));
+                &residency.continents
+            } else if let Some((continent, _)) = CONTINENTS
+                .iter()
+                .find(|(_, code)| code.eq_ignore_ascii_case(location))
+            {
+                // some of them are country codes too (e.g. SA for Saudi Arabia)
+                anyhow::bail!(
+                    "Invalid data residency location: {location} (continent codes are ambiguous, use {continent} instead)"
+                );
+            } else if location.len() == 2 && location.chars().all(|c| c.is_ascii_alphabetic()) {
+                let country_code = location.to_ascii_uppercase();
+                residency.countries.push((country_code, destination));
+                &residency.countries
+            } else {
+                anyhow::bail!(
+                    "Invalid data residency location: {location} (expected a country code or one of {})",
+                    CONTINENTS
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            };
+            let (last, others) = locations.split_last().expect("just pushed");
+            if others.iter().any(|(location, _)| *location == last.0) {
+                anyhow::bail!("Duplicate data residency location: {}", last.0);
+            }
+        }
+
+        if residency == Self::default() {
+            return Ok(None);
+        }
+        Ok(Some(residency))
+    }
+
+    pub fn destination(&self, edgee_event: &Event) -> Option<&Destination> {
+        let client = &edgee_event.context.client;
+        self.countries
+            .iter()
+            .find(|(country_code, _)| country_code.eq_ignore_ascii_case(&client.country_code))
+            .or_else(|| {
+                let continent = continent_name(&client.continent)?;
+                self.continents.iter().find(|(name, _)| name == continent)
+            })
+            .map(|(_, destination)| destination)
+    }
+}
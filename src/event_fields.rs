@@ -0,0 +1,487 @@
+use crate::exports::edgee::components::data_collection::{
+    Campaign, Client, Consent, Data, Dict, Event, EventType, PageData, Session, UserData,
+};
+use chrono::offset::Utc;
+use chrono::DateTime;
+use std::time::SystemTime;
+
+const EVENT_FIELDS: &[&str] = &[
+    "uuid",
+    "timestamp",
+    "timestamp_millis",
+    "timestamp_micros",
+    "event_type",
+    "consent",
+];
+
+const PAGE_FIELDS: &[&str] = &[
+    "name", "category", "keywords", "title", "url", "path", "search", "referrer",
+];
+
+const USER_FIELDS: &[&str] = &["user_id", "anonymous_id", "edgee_id"];
+
+const CLIENT_FIELDS: &[&str] = &[
+    "city",
+    "ip",
+    "locale",
+    "timezone",
+    "user_agent",
+    "user_agent_architecture",
+    "user_agent_bitness",
+    "user_agent_full_version_list",
+    "user_agent_version_list",
+    "user_agent_mobile",
+    "user_agent_model",
+    "os_name",
+    "os_version",
+    "screen_width",
+    "screen_height",
+    "screen_density",
+    "continent",
+    "country_code",
+    "country_name",
+    "region",
+];
+
+const CAMPAIGN_FIELDS: &[&str] = &[
+    "name",
+    "source",
+    "medium",
+    "term",
+    "content",
+    "creative_format",
+    "marketing_tactic",
+];
+
+const SESSION_FIELDS: &[&str] = &[
+    "session_id",
+    "previous_session_id",
+    "session_count",
+    "session_start",
+    "first_seen",
+    "last_seen",
+];
+
+/// Fields of `data`, whatever the event type (page, track or user data).
+const DATA_FIELDS: &[&str] = &[
+    "name",
+    "category",
+    "keywords",
+    "title",
+    "url",
+    "path",
+    "search",
+    "referrer",
+    "user_id",
+    "anonymous_id",
+    "edgee_id",
+];
+
+/// Checks that a dotted path (e.g. `context.client.country_code`) points to a
+/// known event field. Properties accept any key (e.g. `data.properties.email`).
+pub fn is_known_field(path: &str) -> bool {
+    if EVENT_FIELDS.contains(&path) {
+        return true;
+    }
+    let Some((head, rest)) = path.split_once('.') else {
+        return false;
+    };
+    match head {
+        "data" => is_known_in(rest, DATA_FIELDS),
+        "context" => match rest.split_once('.') {
+            Some(("page", field)) => is_known_in(field, PAGE_FIELDS),
+            Some(("user", field)) => is_known_in(field, USER_FIELDS),
+            Some(("client", field)) => CLIENT_FIELDS.contains(&field),
+            Some(("campaign", field)) => CAMPAIGN_FIELDS.contains(&field),
+            Some(("session", field)) => SESSION_FIELDS.contains(&field),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn is_known_in(field: &str, fields: &[&str]) -> bool {
+    match field.split_once('.') {
+        Some(("properties", key)) => !key.is_empty(),
+        Some(_) => false,
+        None => fields.contains(&field),
+    }
+}
+
+/// Checks that a dotted path points to a known text field, whose value can be rewritten
+/// (numbers, booleans and enums can't hold arbitrary strings).
+pub fn is_known_text_field(path: &str) -> bool {
+    const NON_TEXT_FIELDS: &[&str] = &[
+        "timestamp",
+        "timestamp_millis",
+        "timestamp_micros",
+        "event_type",
+        "consent",
+        "context.client.screen_width",
+        "context.client.screen_height",
+        "context.client.screen_density",
+        "context.session.session_count",
+        "context.session.session_start",
+        "context.session.first_seen",
+        "context.session.last_seen",
+    ];
+    is_known_field(path) && !NON_TEXT_FIELDS.contains(&path)
+}
+
+/// Paths of the properties of the event: `data.properties` (page, track and user data),
+/// and those of the context page and user.
+pub const PROPERTIES_PATHS: &[&str] = &[
+    "data.properties",
+    "context.page.properties",
+    "context.user.properties",
+];
+
+/// Every field of the event as a dotted path, with properties and products as single fields.
+pub fn leaf_fields() -> Vec<String> {
+    let mut fields: Vec<String> = EVENT_FIELDS.iter().map(|field| field.to_string()).collect();
+    for (section, section_fields) in [
+        ("data", DATA_FIELDS),
+        ("context.page", PAGE_FIELDS),
+        ("context.user", USER_FIELDS),
+        ("context.client", CLIENT_FIELDS),
+        ("context.campaign", CAMPAIGN_FIELDS),
+        ("context.session", SESSION_FIELDS),
+    ] {
+        fields.extend(
+            section_fields
+                .iter()
+                .map(|field| format!("{section}.{field}")),
+        );
+    }
+    fields.push("data.products".to_string());
+    fields.extend(PROPERTIES_PATHS.iter().map(|path| path.to_string()));
+    fields
+}
+
+/// Resolves a dotted path against an event, returning its value as a string.
+/// Returns None when the field doesn't exist for this event
+/// (e.g. `data.user_id` on a page event, or a missing property).
+pub fn resolve(edgee_event: &Event, path: &str) -> Option<String> {
+    match path {
+        "uuid" => return Some(edgee_event.uuid.clone()),
+        "timestamp" => return Some(edgee_event.timestamp.to_string()),
+        "timestamp_millis" => return Some(edgee_event.timestamp_millis.to_string()),
+        "timestamp_micros" => return Some(edgee_event.timestamp_micros.to_string()),
+        "event_type" => return Some(event_type_name(&edgee_event.event_type).to_string()),
+        "consent" => return Some(consent_name(edgee_event.consent.as_ref()).to_string()),
+        _ => {}
+    }
+
+    let (head, rest) = path.split_once('.')?;
+    match head {
+        "data" => match &edgee_event.data {
+            Data::Page(page) => resolve_page(page, rest),
+            Data::Track(track) => match rest {
+                "name" => Some(track.name.clone()),
+                _ => resolve_properties(&track.properties, rest),
+            },
+            Data::User(user) => resolve_user(user, rest),
+        },
+        "context" => {
+            let context = &edgee_event.context;
+            let (section, field) = rest.split_once('.')?;
+            match section {
+                "page" => resolve_page(&context.page, field),
+                "user" => resolve_user(&context.user, field),
+                "client" => resolve_client(&context.client, field),
+                "campaign" => resolve_campaign(&context.campaign, field),
+                "session" => resolve_session(&context.session, field),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn resolve_properties(properties: &Dict, path: &str) -> Option<String> {
+    let key = path.strip_prefix("properties.")?;
+    properties
+        .iter()
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value.clone())
+}
+
+fn resolve_page(page: &PageData, field: &str) -> Option<String> {
+    match field {
+        "name" => Some(page.name.clone()),
+        "category" => Some(page.category.clone()),
+        "keywords" => Some(page.keywords.join(",")),
+        "title" => Some(page.title.clone()),
+        "url" => Some(page.url.clone()),
+        "path" => Some(page.path.clone()),
+        "search" => Some(page.search.clone()),
+        "referrer" => Some(page.referrer.clone()),
+        _ => resolve_properties(&page.properties, field),
+    }
+}
+
+fn resolve_user(user: &UserData, field: &str) -> Option<String> {
+    match field {
+        "user_id" => Some(user.user_id.clone()),
+        "anonymous_id" => Some(user.anonymous_id.clone()),
+        "edgee_id" => Some(user.edgee_id.clone()),
+        _ => resolve_properties(&user.properties, field),
+    }
+}
+
+fn resolve_client(client: &Client, field: &str) -> Option<String> {
+    let value = match field {
+        "city" => client.city.clone(),
+        "ip" => client.ip.clone(),
+        "locale" => client.locale.clone(),
+        "timezone" => client.timezone.clone(),
+        "user_agent" => client.user_agent.clone(),
+        "user_agent_architecture" => client.user_agent_architecture.clone(),
+        "user_agent_bitness" => client.user_agent_bitness.clone(),
+        "user_agent_full_version_list" => client.user_agent_full_version_list.clone(),
+        "user_agent_version_list" => client.user_agent_version_list.clone(),
+        "user_agent_mobile" => client.user_agent_mobile.clone(),
+        "user_agent_model" => client.user_agent_model.clone(),
+        "os_name" => client.os_name.clone(),
+        "os_version" => client.os_version.clone(),
+        "screen_width" => client.screen_width.to_string(),
+        "screen_height" => client.screen_height.to_string(),
+        "screen_density" => client.screen_density.to_string(),
+        "continent" => client.continent.clone(),
+        "country_code" => client.country_code.clone(),
+        "country_name" => client.country_name.clone(),
+        "region" => client.region.clone(),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn resolve_campaign(campaign: &Campaign, field: &str) -> Option<String> {
+    let value = match field {
+        "name" => campaign.name.clone(),
+        "source" => campaign.source.clone(),
+        "medium" => campaign.medium.clone(),
+        "term" => campaign.term.clone(),
+        "content" => campaign.content.clone(),
+        "creative_format" => campaign.creative_format.clone(),
+        "marketing_tactic" => campaign.marketing_tactic.clone(),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn resolve_session(session: &Session, field: &str) -> Option<String> {
+    let value = match field {
+        "session_id" => session.session_id.clone(),
+        "previous_session_id" => session.previous_session_id.clone(),
+        "session_count" => session.session_count.to_string(),
+        "session_start" => session.session_start.to_string(),
+        "first_seen" => session.first_seen.to_string(),
+        "last_seen" => session.last_seen.to_string(),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Calls `update` on every text value a dotted path points to: a single field,
+/// each keyword of `keywords`, or each entry of a property (a key may be repeated).
+/// Paths that don't exist for this event are ignored.
+pub fn update_text_field(edgee_event: &mut Event, path: &str, mut update: impl FnMut(&mut String)) {
+    if path == "uuid" {
+        update(&mut edgee_event.uuid);
+        return;
+    }
+
+    let Some((head, rest)) = path.split_once('.') else {
+        return;
+    };
+    match head {
+        "data" => match &mut edgee_event.data {
+            Data::Page(page) => update_page(page, rest, update),
+            Data::Track(track) => match rest {
+                "name" => update(&mut track.name),
+                _ => update_properties(&mut track.properties, rest, update),
+            },
+            Data::User(user) => update_user(user, rest, update),
+        },
+        "context" => {
+            let context = &mut edgee_event.context;
+            let Some((section, field)) = rest.split_once('.') else {
+                return;
+            };
+            match section {
+                "page" => update_page(&mut context.page, field, update),
+                "user" => update_user(&mut context.user, field, update),
+                "client" => {
+                    if let Some(value) = client_text_mut(&mut context.client, field) {
+                        update(value);
+                    }
+                }
+                "campaign" => {
+                    if let Some(value) = campaign_text_mut(&mut context.campaign, field) {
+                        update(value);
+                    }
+                }
+                "session" => match field {
+                    "session_id" => update(&mut context.session.session_id),
+                    "previous_session_id" => update(&mut context.session.previous_session_id),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+}
+
+fn update_properties(properties: &mut Dict, path: &str, mut update: impl FnMut(&mut String)) {
+    let Some(key) = path.strip_prefix("properties.") else {
+        return;
+    };
+    properties
+        .iter_mut()
+        .filter(|(name, _)| name == key)
+        .for_each(|(_, value)| update(value));
+}
+
+fn update_page(page: &mut PageData, field: &str, mut update: impl FnMut(&mut String)) {
+    match field {
+        "name" => update(&mut page.name),
+        "category" => update(&mut page.category),
+        "keywords" => page.keywords.iter_mut().for_each(update),
+        "title" => update(&mut page.title),
+        "url" => update(&mut page.url),
+        "path" => update(&mut page.path),
+        "search" => update(&mut page.search),
+        "referrer" => update(&mut page.referrer),
+        _ => update_properties(&mut page.properties, field, update),
+    }
+}
+
+fn update_user(user: &mut UserData, field: &str, mut update: impl FnMut(&mut String)) {
+    match field {
+        "user_id" => update(&mut user.user_id),
+        "anonymous_id" => update(&mut user.anonymous_id),
+        "edgee_id" => update(&mut user.edgee_id),
+        _ => update_properties(&mut user.properties, field, update),
+    }
+}
+
+fn client_text_mut<'a>(client: &'a mut Client, field: &str) -> Option<&'a mut String> {
+    let value = match field {
+        "city" => &mut client.city,
+        "ip" => &mut client.ip,
+        "locale" => &mut client.locale,
+        "timezone" => &mut client.timezone,
+        "user_agent" => &mut client.user_agent,
+        "user_agent_architecture" => &mut client.user_agent_architecture,
+        "user_agent_bitness" => &mut client.user_agent_bitness,
+        "user_agent_full_version_list" => &mut client.user_agent_full_version_list,
+        "user_agent_version_list" => &mut client.user_agent_version_list,
+        "user_agent_mobile" => &mut client.user_agent_mobile,
+        "user_agent_model" => &mut client.user_agent_model,
+        "os_name" => &mut client.os_name,
+        "os_version" => &mut client.os_version,
+        "continent" => &mut client.continent,
+        "country_code" => &mut client.country_code,
+        "country_name" => &mut client.country_name,
+        "region" => &mut client.region,
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn campaign_text_mut<'a>(campaign: &'a mut Campaign, field: &str) -> Option<&'a mut String> {
+    let value = match field {
+        "name" => &mut campaign.name,
+        "source" => &mut campaign.source,
+        "medium" => &mut campaign.medium,
+        "term" => &mut campaign.term,
+        "content" => &mut campaign.content,
+        "creative_format" => &mut campaign.creative_format,
+        "marketing_tactic" => &mut campaign.marketing_tactic,
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Empties a field returned by `leaf_fields`: text is cleared, numbers are set to zero,
+/// booleans to false, consent to unknown, and lists and properties are emptied.
+/// `event_type` can't be emptied.
+pub fn clear_field(edgee_event: &mut Event, path: &str) {
+    let client = &mut edgee_event.context.client;
+    let session = &mut edgee_event.context.session;
+    match path {
+        "timestamp" => edgee_event.timestamp = 0,
+        "timestamp_millis" => edgee_event.timestamp_millis = 0,
+        "timestamp_micros" => edgee_event.timestamp_micros = 0,
+        "event_type" => {}
+        "consent" => edgee_event.consent = None,
+        "context.client.screen_width" => client.screen_width = 0,
+        "context.client.screen_height" => client.screen_height = 0,
+        "context.client.screen_density" => client.screen_density = 0.0,
+        "context.session.session_count" => session.session_count = 0,
+        "context.session.session_start" => session.session_start = false,
+        "context.session.first_seen" => session.first_seen = 0,
+        "context.session.last_seen" => session.last_seen = 0,
+        "context.page.keywords" => edgee_event.context.page.keywords.clear(),
+        "data.keywords" => {
+            if let Data::Page(page) = &mut edgee_event.data {
+                page.keywords.clear();
+            }
+        }
+        "data.products" => {
+            if let Data::Track(track) = &mut edgee_event.data {
+                track.products.clear();
+            }
+        }
+        _ => match properties_mut(edgee_event, path) {
+            Some(properties) => properties.clear(),
+            None => update_text_field(edgee_event, path, String::clear),
+        },
+    }
+}
+
+/// Returns the properties one of `PROPERTIES_PATHS` points to.
+pub fn properties_mut<'a>(edgee_event: &'a mut Event, path: &str) -> Option<&'a mut Dict> {
+    match path {
+        "data.properties" => Some(match &mut edgee_event.data {
+            Data::Page(page) => &mut page.properties,
+            Data::Track(track) => &mut track.properties,
+            Data::User(user) => &mut user.properties,
+        }),
+        "context.page.properties" => Some(&mut edgee_event.context.page.properties),
+        "context.user.properties" => Some(&mut edgee_event.context.user.properties),
+        _ => None,
+    }
+}
+
+pub fn event_type_name(event_type: &EventType) -> &'static str {
+    match event_type {
+        EventType::Page => "page",
+        EventType::Track => "track",
+        EventType::User => "user",
+    }
+}
+
+pub fn consent_name(consent: Option<&Consent>) -> &'static str {
+    match consent {
+        Some(Consent::Granted) => "granted",
+        Some(Consent::Denied) => "denied",
+        Some(Consent::Pending) => "pending",
+        None => "unknown",
+    }
+}
+
+/// Event time used for keys and partitions, falling back to the current time
+/// when the event doesn't carry a usable timestamp.
+pub fn event_datetime(edgee_event: &Event) -> DateTime<Utc> {
+    let datetime = if edgee_event.timestamp_millis > 0 {
+        DateTime::from_timestamp_millis(edgee_event.timestamp_millis)
+    } else if edgee_event.timestamp > 0 {
+        DateTime::from_timestamp(edgee_event.timestamp, 0)
+    } else {
+        None
+    };
+    datetime.unwrap_or_else(|| SystemTime::now().into())
+}
@@ -0,0 +1,94 @@
+use crate::event_fields::event_type_name;
+use crate::exports::edgee::components::data_collection::EventType;
+use crate::output_format::OutputFormat;
+use crate::routing::Destination;
+use crate::s3_key::check_key_prefix;
+use crate::s3_payload::{check_bucket_name, get_optional_setting};
+use anyhow::Context;
+use std::collections::HashMap;
+
+/// Settings of a single event type: whether it's sent, and where and how it's stored.
+/// The storage class override lives in `StorageClasses`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventTypeSettings {
+    pub enabled: bool,
+    pub destination: Destination,
+    pub output_format: Option<OutputFormat>, // None means the default output format
+}
+
+impl EventTypeSettings {
+    fn new(settings_map: &HashMap<String, String>, event_type: &str) -> anyhow::Result<Self> {
+        let enabled_key = format!("edgee_{event_type}_event_enabled");
+        let enabled = match settings_map.get(&enabled_key).map(String::as_str) {
+            None | Some("") => true,
+            Some(value) => value
+                .parse::<bool>()
+                .with_context(|| format!("Invalid boolean value for {enabled_key}: {value}"))?,
+        };
+
+        let output_format =
+            get_optional_setting(settings_map, &format!("{event_type}_output_format"))
+                .map(|format| OutputFormat::parse(&format, settings_map))
+                .transpose()?;
+
+        let bucket_key = format!("{event_type}_s3_bucket");
+        let bucket = get_optional_setting(settings_map, &bucket_key);
+        if let Some(bucket) = &bucket {
+            check_bucket_name(settings_map, &bucket_key, bucket)?;
+        }
+        let key_prefix_key = format!("{event_type}_s3_key_prefix");
+        let key_prefix = get_optional_setting(settings_map, &key_prefix_key);
+        if let Some(key_prefix) = &key_prefix {
+            check_key_prefix(&key_prefix_key, key_prefix)?;
+        }
+
+        Ok(Self {
+            enabled,
+            destination: Destination {
+                bucket,
+                key_prefix,
+                ..Destination::default()
+            },
+            output_format,
+        })
+    }
+}
+
+/// Per-event-type settings, from the `edgee_<type>_event_enabled`
+/// and `<type>_s3_bucket`, `<type>_s3_key_prefix`, `<type>_output_format` settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventTypes {
+    pub page: EventTypeSettings,
+    pub track: EventTypeSettings,
+    pub user: EventTypeSettings,
+}
+
+impl EventTypes {
+    pub fn new(settings_map: &HashMap<String, String>) -> anyhow::Result<Self> {
+        Ok(Self {
+            page: EventTypeSettings::new(settings_map, "page")?,
+            track: EventTypeSettings::new(settings_map, "track")?,
+            user: EventTypeSettings::new(settings_map, "user")?,
+        })
+    }
+
+    pub fn for_event_type(&self, event_type: &EventType) -> &EventTypeSettings {
+        match event_type {
+            EventType::Page => &self.page,
+            EventType::Track => &self.track,
+            EventType::User => &self.user,
+        }
+    }
+
+    /// Returns the settings of the event type, or an error for disabled event types.
+    pub fn enabled(&self, event_type: &EventType) -> anyhow::Result<&EventTypeSettings> {
+        let settings = self.for_event_type(event_type);
+        if !settings.enabled {
+            let name = event_type_name(event_type);
+            anyhow::bail!(
+                "Events of type {name} are disabled (edgee_{name}_event_enabled = false)"
+            );
+        }
+        Ok(settings)
+    }
+}
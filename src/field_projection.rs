@@ -0,0 +1,103 @@
+use crate::event_fields;
+use crate::exports::edgee::components::data_collection::Event;
+use crate::s3_payload::{get_setting, split_list};
+use std::collections::HashMap;
+
+/// Sections of the event that can be included or excluded as a whole.
+const SECTIONS: &[&str] = &[
+    "data",
+    "context",
+    "context.page",
+    "context.user",
+    "context.client",
+    "context.campaign",
+    "context.session",
+];
+
+/// Fields kept in the stored objects.
+/// Other fields are emptied rather than removed, so that every output format keeps its schema.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldProjection {
+    /// Fields or sections to keep, all of them when empty.
+    pub include: Vec<String>,
+    /// Fields or sections to empty, after `include`.
+    pub exclude: Vec<String>,
+}
+
+impl FieldProjection {
+    pub fn new(settings_map: &HashMap<String, String>) -> anyhow::Result<Option<Self>> {
+        let projection = Self {
+            include: parse_fields(get_setting(settings_map, "include_fields"), "included")?,
+            exclude: parse_fields(get_setting(settings_map, "exclude_fields"), "excluded")?,
+        };
+        if projection.exclude.iter().any(|path| path == "event_type") {
+            anyhow::bail!("event_type can't be excluded");
+        }
+
+        if projection.include.is_empty() && projection.exclude.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(projection))
+    }
+
+    pub fn apply(&self, edgee_event: &mut Event) {
+        for field in event_fields::leaf_fields() {
+            let excluded = (!self.include.is_empty() && !self.includes(&field))
+                || self.exclude.iter().any(|path| contains(path, &field));
+            if excluded {
+                event_fields::clear_field(edgee_event, &field);
+            }
+        }
+
+        // properties can also be projected key by key
+        for properties_path in event_fields::PROPERTIES_PATHS {
+            let Some(properties) = event_fields::properties_mut(edgee_event, properties_path)
+            else {
+                continue;
+            };
+            let included_as_a_whole = self.include.is_empty()
+                || self
+                    .include
+                    .iter()
+                    .any(|path| contains(path, properties_path));
+            properties.retain(|(key, _)| {
+                let path = format!("{properties_path}.{key}");
+                (included_as_a_whole || self.include.contains(&path))
+                    && !self.exclude.contains(&path)
+            });
+        }
+    }
+
+    /// Whether some of the field is included: the field itself, one of its sections,
+    /// or (for properties) some of its keys.
+    fn includes(&self, field: &str) -> bool {
+        field == "event_type"
+            || self
+                .include
+                .iter()
+                .any(|path| contains(path, field) || contains(field, path))
+    }
+}
+
+/// Whether `path` is `section` or one of its fields.
+fn contains(section: &str, path: &str) -> bool {
+    path == section
+        || path
+            .strip_prefix(section)
+            .is_some_and(|rest| rest.starts_with('.'))
+}
+
+fn parse_fields(fields: &str, kind: &str) -> anyhow::Result<Vec<String>> {
+    split_list(fields)
+        .map(|field| {
+            if !event_fields::is_known_field(field)
+                && !SECTIONS.contains(&field)
+                && !event_fields::PROPERTIES_PATHS.contains(&field)
+                && field != "data.products"
+            {
+                anyhow::bail!("Unknown {kind} field: {field}");
+            }
+            Ok(field.to_string())
+        })
+        .collect()
+}
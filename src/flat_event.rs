@@ -0,0 +1,181 @@
+use crate::event_fields::{consent_name, event_type_name};
+use crate::exports::edgee::components::data_collection::{Data, Dict, Event};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+/// A flattened event, with one snake_case column per field,
+/// so that it's easy to query with Athena, Snowflake or BigQuery.
+///
+/// All columns are always present (null or empty when they don't apply)
+/// to keep the schema stable across event types.
+#[derive(serde::Serialize)]
+pub struct FlatEvent<'a> {
+    pub uuid: &'a str,
+    pub timestamp: i64,
+    pub timestamp_millis: i64,
+    pub timestamp_micros: i64,
+    pub event_type: &'static str,
+    pub consent: &'static str,
+    pub sample_rate: Option<f64>, // None when sampling is disabled
+
+    // data
+    pub track_name: Option<&'a str>,
+    pub properties: Properties<'a>,
+    pub products: Vec<Properties<'a>>,
+
+    // context.page
+    pub page_name: &'a str,
+    pub page_category: &'a str,
+    pub page_keywords: &'a [String],
+    pub page_title: &'a str,
+    pub page_url: &'a str,
+    pub page_path: &'a str,
+    pub page_search: &'a str,
+    pub page_referrer: &'a str,
+    pub page_properties: Properties<'a>,
+
+    // context.user
+    pub user_id: &'a str,
+    pub user_anonymous_id: &'a str,
+    pub user_edgee_id: &'a str,
+    pub user_properties: Properties<'a>,
+
+    // context.client
+    pub client_city: &'a str,
+    pub client_ip: &'a str,
+    pub client_locale: &'a str,
+    pub client_timezone: &'a str,
+    pub client_user_agent: &'a str,
+    pub client_user_agent_architecture: &'a str,
+    pub client_user_agent_bitness: &'a str,
+    pub client_user_agent_full_version_list: &'a str,
+    pub client_user_agent_version_list: &'a str,
+    pub client_user_agent_mobile: &'a str,
+    pub client_user_agent_model: &'a str,
+    pub client_os_name: &'a str,
+    pub client_os_version: &'a str,
+    pub client_screen_width: i32,
+    pub client_screen_height: i32,
+    pub client_screen_density: f32,
+    pub client_continent: &'a str,
+    pub client_country_code: &'a str,
+    pub client_country_name: &'a str,
+    pub client_region: &'a str,
+
+    // context.campaign
+    pub campaign_name: &'a str,
+    pub campaign_source: &'a str,
+    pub campaign_medium: &'a str,
+    pub campaign_term: &'a str,
+    pub campaign_content: &'a str,
+    pub campaign_creative_format: &'a str,
+    pub campaign_marketing_tactic: &'a str,
+
+    // context.session
+    pub session_id: &'a str,
+    pub previous_session_id: &'a str,
+    pub session_count: u32,
+    pub session_start: bool,
+    pub session_first_seen: i64,
+    pub session_last_seen: i64,
+}
+
+/// Key/value pairs, serialized as a JSON object instead of a list of tuples.
+pub struct Properties<'a>(pub &'a Dict);
+
+impl Serialize for Properties<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in self.0 {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'a> FlatEvent<'a> {
+    pub fn new(edgee_event: &'a Event, sample_rate: Option<f64>) -> Self {
+        let (track_name, properties, products) = match &edgee_event.data {
+            Data::Page(page) => (None, &page.properties, vec![]),
+            Data::Track(track) => (
+                Some(track.name.as_str()),
+                &track.properties,
+                track.products.iter().map(Properties).collect(),
+            ),
+            Data::User(user) => (None, &user.properties, vec![]),
+        };
+
+        let context = &edgee_event.context;
+        let (page, user, client, campaign, session) = (
+            &context.page,
+            &context.user,
+            &context.client,
+            &context.campaign,
+            &context.session,
+        );
+
+        Self {
+            uuid: &edgee_event.uuid,
+            timestamp: edgee_event.timestamp,
+            timestamp_millis: edgee_event.timestamp_millis,
+            timestamp_micros: edgee_event.timestamp_micros,
+            event_type: event_type_name(&edgee_event.event_type),
+            consent: consent_name(edgee_event.consent.as_ref()),
+            sample_rate,
+
+            track_name,
+            properties: Properties(properties),
+            products,
+
+            page_name: &page.name,
+            page_category: &page.category,
+            page_keywords: &page.keywords,
+            page_title: &page.title,
+            page_url: &page.url,
+            page_path: &page.path,
+            page_search: &page.search,
+            page_referrer: &page.referrer,
+            page_properties: Properties(&page.properties),
+
+            user_id: &user.user_id,
+            user_anonymous_id: &user.anonymous_id,
+            user_edgee_id: &user.edgee_id,
+            user_properties: Properties(&user.properties),
+
+            client_city: &client.city,
+            client_ip: &client.ip,
+            client_locale: &client.locale,
+            client_timezone: &client.timezone,
+            client_user_agent: &client.user_agent,
+            client_user_agent_architecture: &client.user_agent_architecture,
+            client_user_agent_bitness: &client.user_agent_bitness,
+            client_user_agent_full_version_list: &client.user_agent_full_version_list,
+            client_user_agent_version_list: &client.user_agent_version_list,
+            client_user_agent_mobile: &client.user_agent_mobile,
+            client_user_agent_model: &client.user_agent_model,
+            client_os_name: &client.os_name,
+            client_os_version: &client.os_version,
+            client_screen_width: client.screen_width,
+            client_screen_height: client.screen_height,
+            client_screen_density: client.screen_density,
+            client_continent: &client.continent,
+            client_country_code: &client.country_code,
+            client_country_name: &client.country_name,
+            client_region: &client.region,
+
+            campaign_name: &campaign.name,
+            campaign_source: &campaign.source,
+            campaign_medium: &campaign.medium,
+            campaign_term: &campaign.term,
+            campaign_content: &campaign.content,
+            campaign_creative_format: &campaign.creative_format,
+            campaign_marketing_tactic: &campaign.marketing_tactic,
+
+            session_id: &session.session_id,
+            previous_session_id: &session.previous_session_id,
+            session_count: session.session_count,
+            session_start: session.session_start,
+            session_first_seen: session.first_seen,
+            session_last_seen: session.last_seen,
+        }
+    }
+}
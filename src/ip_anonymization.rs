@@ -0,0 +1,118 @@
+use crate::exports::edgee::components::data_collection::Event;
+use crate::keyed_hash::keyed_hash;
+use crate::s3_payload::get_setting;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// How `context.client.ip` is written to S3.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum IpAnonymization {
+    /// The IP is kept as is.
+    #[default]
+    None,
+    /// The last octet of IPv4 addresses and the last 80 bits of IPv6 addresses are zeroed.
+    Truncate,
+    /// The IP is replaced by its HMAC-SHA256, keyed with a secret salt.
+    Hash { salt: String },
+    /// The IP is removed.
+    Drop,
+}
+
+impl IpAnonymization {
+    pub fn new(settings_map: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let salt = get_setting(settings_map, "ip_hash_salt");
+
+        let anonymization = match get_setting(settings_map, "ip_anonymization") {
+            "" | "none" => Self::None,
+            "truncate" => Self::Truncate,
+            "hash" => {
+                if salt.is_empty() {
+                    anyhow::bail!("IP hashing requires a secret ip_hash_salt");
+                }
+                return Ok(Self::Hash {
+                    salt: salt.to_string(),
+                });
+            }
+            "drop" => Self::Drop,
+            other => anyhow::bail!(
+                "Invalid IP anonymization: {other} (expected none, truncate, hash or drop)"
+            ),
+        };
+
+        if !salt.is_empty() {
+            anyhow::bail!("ip_hash_salt requires ip_anonymization = hash");
+        }
+        Ok(anonymization)
+    }
+
+    pub fn apply(&self, edgee_event: &mut Event) {
+        let ip = &mut edgee_event.context.client.ip;
+        if ip.is_empty() {
+            return;
+        }
+        match self {
+            Self::None => {}
+            Self::Truncate => *ip = truncate_ip(ip).unwrap_or_default(),
+            Self::Hash { salt } => *ip = keyed_hash(salt, ip),
+            Self::Drop => ip.clear(),
+        }
+    }
+}
+
+/// Zeroes the host part of an IP address, or returns None if it can't be parsed
+/// (so that an unknown format never leaks a full address).
+fn truncate_ip(ip: &str) -> Option<String> {
+    let ip: IpAddr = ip.trim().parse().ok()?;
+    let truncated = match ip {
+        IpAddr::V4(ipv4) => truncate_ipv4(ipv4).to_string(),
+        IpAddr::V6(ipv6) => match ipv6.to_ipv4_mapped() {
+            // IPv4-mapped IPv6 addresses (::ffff:a.b.c.d) carry an IPv4 address
+            Some(ipv4) => truncate_ipv4(ipv4).to_ipv6_mapped().to_string(),
+            None => truncate_ipv6(ipv6).to_string(),
+        },
+    };
+    Some(truncated)
+}
+
+fn truncate_ipv4(ipv4: Ipv4Addr) -> Ipv4Addr {
+    Ipv4Addr::from(ipv4.to_bits() & !0xff)
+}
+
+fn truncate_ipv6(ipv6: Ipv6Addr) -> Ipv6Addr {
+    Ipv6Addr::from(ipv6.to_bits() & !((1u128 << 80) - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn truncates_ip_addresses() {
+        for (ip, truncated) in [
+            ("192.168.0.1", Some("192.168.0.0")),
+            ("8.8.8.8", Some("8.8.8.0")),
+            ("255.255.255.255", Some("255.255.255.0")),
+            (" 10.1.2.3 ", Some("10.1.2.0")),
+            (
+                "2001:db8:85a3:8d3:1319:8a2e:370:7348",
+                Some("2001:db8:85a3::"),
+            ),
+            ("2001:db8::1", Some("2001:db8::")),
+            ("::1", Some("::")),
+            // IPv4-mapped IPv6 addresses are truncated as IPv4 addresses
+            ("::ffff:192.168.0.1", Some("::ffff:192.168.0.0")),
+            ("::ffff:c0a8:1", Some("::ffff:192.168.0.0")),
+            // but not IPv4-compatible ones, which are plain IPv6 addresses
+            ("::192.168.0.1", Some("::")),
+            // unparseable addresses are removed
+            ("192.168.0", None),
+            ("192.168.0.1:8080", None),
+            ("[2001:db8::1]", None),
+            ("fe80::1%eth0", None),
+            ("not an ip", None),
+        ] {
+            assert_eq!(truncate_ip(ip).as_deref(), truncated, "{ip}");
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// HMAC-SHA256 of the value, in hex.
+/// The same value and salt always give the same hash, so hashed values stay joinable,
+/// but they can't be reversed (or brute-forced) without the salt.
+pub fn keyed_hash(salt: &str, value: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(salt.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(value.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
@@ -1,6 +1,25 @@
 use crate::exports::edgee::components::data_collection::{Dict, EdgeeRequest, Event, HttpMethod};
 use exports::edgee::components::data_collection::Guest;
+mod consent_policy;
+mod data_residency;
+mod event_fields;
+mod event_types;
+mod field_projection;
+mod flat_event;
+mod ip_anonymization;
+mod keyed_hash;
+mod output_format;
+mod pseudonymization;
+mod routing;
+mod s3_checksum;
+mod s3_encryption;
+mod s3_key;
+mod s3_metadata;
 mod s3_payload;
+mod s3_storage_class;
+mod s3_tagging;
+mod sampling;
+mod track_names;
 
 wit_bindgen::generate!({
     world: "data-collection",
@@ -27,25 +46,80 @@ impl Guest for Component {
 }
 
 fn send_to_s3(edgee_event: Event, settings_dict: Dict) -> Result<EdgeeRequest, String> {
-    let s3_settings = s3_payload::Settings::new(settings_dict).map_err(|e| e.to_string())?;
+    // skipped events (disabled event types, filtered out track names, sampled out events
+    // and events dropped by the consent policy) are errors too, so that the host doesn't send them
+    let upload = prepare_s3_upload(&edgee_event, settings_dict).map_err(|e| e.to_string())?;
 
-    // serialize the entire event into JSON
-    let file_content = serde_json::to_string(&edgee_event).unwrap_or_default();
+    Ok(EdgeeRequest {
+        method: HttpMethod::Put,
+        url: upload.url,
+        headers: upload.headers,
+        body: upload.body,
+        forward_client_headers: false,
+    })
+}
+
+/// A signed PUT request.
+struct S3Upload {
+    url: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+fn prepare_s3_upload(edgee_event: &Event, settings_dict: Dict) -> anyhow::Result<S3Upload> {
+    let mut s3_settings = s3_payload::Settings::new(settings_dict)?;
+
+    // disabled event types and filtered out track names stop here,
+    // others may have their own destination and output format
+    s3_settings.set_event_type(&edgee_event.event_type)?;
+    s3_settings.set_track_name(edgee_event)?;
+    s3_settings.set_routing_destination(edgee_event);
+
+    // sampled out events stop here, kept ones record their sample rate in the object and its metadata
+    let sample_rate = match &s3_settings.sampling {
+        Some(sampling) => Some(sampling.apply(edgee_event)?),
+        None => None,
+    };
+    if let Some(sample_rate) = sample_rate {
+        s3_settings.metadata.set_sample_rate(sample_rate);
+    }
+
+    let mut edgee_event = edgee_event.clone();
+
+    // dropped events stop here, others may be anonymized or routed depending on consent
+    if let Some(destination) = s3_settings.consent_policy.apply(&mut edgee_event)?.cloned() {
+        s3_settings.set_destination(&destination);
+    }
+    // the region and bucket of the client's location win over all other destinations
+    s3_settings.set_data_residency(&edgee_event);
+    s3_settings.ip_anonymization.apply(&mut edgee_event);
+    if let Some(pseudonymization) = &s3_settings.pseudonymization {
+        pseudonymization.apply(&mut edgee_event);
+    }
+    // fields left out by the projection are emptied in the object, its key and its headers alike
+    let projected_event = s3_settings.project(&edgee_event);
+    let edgee_event = projected_event.as_ref();
+
+    // serialize the event in the configured output format (full JSON dump by default)
+    let file_content = s3_settings
+        .output_format
+        .serialize(edgee_event, sample_rate)?;
 
     // generate full URL and HTTP headers
-    let s3_url = s3_settings.generate_s3_url(); // S3 key is auto-generated (.json)
-    let sigv4_headers = s3_settings.generate_s3_headers(s3_url.clone(), file_content.clone());
+    let s3_url = s3_settings.generate_s3_url(edgee_event); // S3 key is auto-generated
+    let sigv4_headers =
+        s3_settings.generate_s3_headers(edgee_event, s3_url.clone(), file_content.as_bytes())?;
 
-    Ok(EdgeeRequest {
-        method: HttpMethod::Put,
+    Ok(S3Upload {
         url: s3_url,
         headers: sigv4_headers,
         body: file_content,
-        forward_client_headers: false,
     })
 }
 
 #[cfg(test)]
+// the sample fixtures predate the clippy gate, keep them as they are
+#[allow(clippy::needless_return, clippy::redundant_field_names)]
 mod tests {
     use super::*;
 
@@ -227,7 +301,7 @@ mod tests {
             true
         );
 
-        assert_eq!(edgee_request.headers.len(), 4);
+        assert_eq!(edgee_request.headers.len(), 5);
         assert_eq!(
             edgee_request
                 .headers
@@ -281,7 +355,7 @@ mod tests {
             true
         );
 
-        assert_eq!(edgee_request.headers.len(), 4);
+        assert_eq!(edgee_request.headers.len(), 5);
         assert_eq!(
             edgee_request
                 .headers
@@ -334,7 +408,7 @@ mod tests {
             true
         );
 
-        assert_eq!(edgee_request.headers.len(), 4);
+        assert_eq!(edgee_request.headers.len(), 5);
         assert_eq!(
             edgee_request
                 .headers
@@ -388,7 +462,7 @@ mod tests {
             true
         );
 
-        assert_eq!(edgee_request.headers.len(), 5);
+        assert_eq!(edgee_request.headers.len(), 6);
         assert_eq!(
             edgee_request
                 .headers
@@ -451,6 +525,2206 @@ mod tests {
         assert_eq!(edgee_request.url.contains("sub-folder/"), true);
     }
 
+    #[test]
+    fn page_with_hourly_partitioning() {
+        let event = sample_page_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("s3_key_prefix".to_string(), "sub-folder/".to_string()),
+            ("s3_key_partitioning".to_string(), "hourly".to_string()),
+            (
+                "s3_key_partition_by_event_type".to_string(),
+                "true".to_string(),
+            ),
+        ];
+        let result = Component::page(event, settings);
+
+        assert_eq!(result.is_err(), false);
+        let edgee_request = result.unwrap();
+        // partitions come from the event timestamp (123ms after epoch)
+        assert_eq!(
+            edgee_request.url.starts_with(
+                "https://test-bucket.s3.eu-west-1.amazonaws.com/sub-folder/event_type=page/year=1970/month=01/day=01/hour=00/"
+            ),
+            true
+        );
+        assert_eq!(edgee_request.url.ends_with(".json"), true);
+    }
+
+    #[test]
+    fn track_with_daily_partitioning() {
+        let mut event = sample_track_event(
+            "custom-event".to_string(),
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+        event.timestamp = 1792300000; // 2026-10-18T05:06:40Z
+        event.timestamp_millis = 0;
+
+        let mut settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("s3_key_partitioning".to_string(), "daily".to_string()),
+        ];
+        let result = Component::track(event.clone(), settings.clone());
+
+        assert_eq!(result.is_err(), false);
+        assert_eq!(
+            result.unwrap().url.starts_with(
+                "https://test-bucket.s3.eu-west-1.amazonaws.com/year=2026/month=10/day=18/"
+            ),
+            true
+        );
+
+        // test with an unknown partitioning
+        settings[4] = ("s3_key_partitioning".to_string(), "weekly".to_string());
+        let result = Component::track(event, settings);
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .contains("Invalid S3 key partitioning"),
+            true
+        );
+    }
+
+    #[test]
+    fn track_with_s3_key_template() {
+        let event = sample_track_event(
+            "custom event".to_string(),
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("s3_key_prefix".to_string(), "sub-folder/".to_string()),
+            (
+                "s3_key_template".to_string(),
+                "{event_type}/{date:%Y/%m/%d}/{context.client.country_code}/{data.name}-{uuid}"
+                    .to_string(),
+            ),
+        ];
+        let result = Component::track(event.clone(), settings);
+
+        assert_eq!(result.is_err(), false);
+        let edgee_request = result.unwrap();
+        assert_eq!(
+            edgee_request.url,
+            format!(
+                "https://test-bucket.s3.eu-west-1.amazonaws.com/sub-folder/track/1970/01/01/FR/custom_event-{}.json",
+                event.uuid
+            )
+        );
+    }
+
+    #[test]
+    fn page_with_idempotent_keys() {
+        let event = sample_page_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("s3_idempotent_keys".to_string(), "true".to_string()),
+            ("s3_if_none_match".to_string(), "true".to_string()),
+        ];
+        let first = Component::page(event.clone(), settings.clone()).unwrap();
+        let retry = Component::page(event.clone(), settings).unwrap();
+
+        // a redelivered event targets the same object
+        assert_eq!(first.url, retry.url);
+        assert_eq!(
+            first.url,
+            format!(
+                "https://test-bucket.s3.eu-west-1.amazonaws.com/1970-01-01-00-00-00-{}.json",
+                event.uuid
+            )
+        );
+
+        assert_eq!(first.headers.len(), 6);
+        assert_eq!(
+            first
+                .headers
+                .iter()
+                .any(|(key, value)| key == "if-none-match" && value == "*"),
+            true
+        );
+        assert_eq!(
+            first
+                .headers
+                .iter()
+                .any(|(key, value)| key == "authorization" && value.contains("if-none-match")),
+            true
+        );
+
+        // templates must identify the event
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("s3_idempotent_keys".to_string(), "true".to_string()),
+            (
+                "s3_key_template".to_string(),
+                "{event_type}/{random}".to_string(),
+            ),
+        ];
+        let result = Component::page(event, settings);
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .contains("must contain {uuid} and no {random}"),
+            true
+        );
+    }
+
+    #[test]
+    fn page_with_custom_endpoint() {
+        let event = sample_page_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+
+        // MinIO on a custom port
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "us-east-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            (
+                "s3_endpoint".to_string(),
+                "http://minio.local:9000".to_string(),
+            ),
+        ];
+        let result = Component::page(event.clone(), settings);
+
+        assert_eq!(result.is_err(), false);
+        let edgee_request = result.unwrap();
+        assert_eq!(
+            edgee_request
+                .url
+                .starts_with("http://test-bucket.minio.local:9000/"),
+            true
+        );
+        assert_eq!(
+            edgee_request
+                .headers
+                .iter()
+                .any(|(key, value)| key == "host" && value == "test-bucket.minio.local:9000"),
+            true
+        );
+
+        // Cloudflare R2 with the "auto" region, default port is dropped
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "auto".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            (
+                "s3_endpoint".to_string(),
+                "https://account-id.r2.cloudflarestorage.com:443/".to_string(),
+            ),
+        ];
+        let result = Component::page(event.clone(), settings);
+
+        assert_eq!(result.is_err(), false);
+        let edgee_request = result.unwrap();
+        assert_eq!(
+            edgee_request
+                .url
+                .starts_with("https://test-bucket.account-id.r2.cloudflarestorage.com/"),
+            true
+        );
+        assert_eq!(
+            edgee_request
+                .headers
+                .iter()
+                .any(|(key, value)| key == "authorization" && value.contains("/auto/s3/")),
+            true
+        );
+
+        // "auto" doesn't make sense on AWS
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "auto".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+        ];
+        let result = Component::page(event.clone(), settings);
+        assert_eq!(result.is_err(), true);
+
+        for endpoint in [
+            "minio.local:9000",
+            "ftp://minio.local",
+            "http://minio.local/path",
+        ] {
+            let settings = vec![
+                ("aws_access_key".to_string(), "TEST".to_string()),
+                ("aws_secret_key".to_string(), "TEST".to_string()),
+                ("aws_region".to_string(), "us-east-1".to_string()),
+                ("s3_bucket".to_string(), "test-bucket".to_string()),
+                ("s3_endpoint".to_string(), endpoint.to_string()),
+            ];
+            let result = Component::page(event.clone(), settings);
+            assert_eq!(result.is_err(), true);
+            assert_eq!(result.err().unwrap().contains("Invalid S3 endpoint"), true);
+        }
+    }
+
+    #[test]
+    fn page_with_path_style() {
+        let event = sample_page_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test.bucket".to_string()),
+            ("s3_key_prefix".to_string(), "sub-folder/".to_string()),
+            ("s3_force_path_style".to_string(), "true".to_string()),
+        ];
+        let result = Component::page(event, settings);
+
+        assert_eq!(result.is_err(), false);
+        let edgee_request = result.unwrap();
+        assert_eq!(
+            edgee_request
+                .url
+                .starts_with("https://s3.eu-west-1.amazonaws.com/test.bucket/sub-folder/"),
+            true
+        );
+        assert_eq!(
+            edgee_request
+                .headers
+                .iter()
+                .any(|(key, value)| key == "host" && value == "s3.eu-west-1.amazonaws.com"),
+            true
+        );
+    }
+
+    #[test]
+    fn track_with_flat_json_output() {
+        let mut event = sample_track_event(
+            "purchase".to_string(),
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+        if let Data::Track(ref mut track) = event.data {
+            track.products = vec![vec![
+                ("sku".to_string(), "SKU-1".to_string()),
+                ("quantity".to_string(), "2".to_string()),
+            ]];
+        }
+
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("output_format".to_string(), "flat_json".to_string()),
+        ];
+        let result = Component::track(event.clone(), settings);
+
+        assert_eq!(result.is_err(), false);
+        let edgee_request = result.unwrap();
+        assert_eq!(edgee_request.url.ends_with(".json"), true);
+        assert_eq!(edgee_request.body.ends_with('\n'), true);
+        assert_eq!(edgee_request.body.lines().count(), 1);
+
+        let row: serde_json::Value = serde_json::from_str(&edgee_request.body).unwrap();
+        assert_eq!(row["uuid"], event.uuid);
+        assert_eq!(row["event_type"], "track");
+        assert_eq!(row["consent"], "granted");
+        assert_eq!(row["sample_rate"], serde_json::Value::Null);
+        assert_eq!(row["track_name"], "purchase");
+        assert_eq!(row["properties"]["currency"], "USD");
+        assert_eq!(row["products"][0]["sku"], "SKU-1");
+        assert_eq!(row["page_url"], "https://example.com/full-url?test=1");
+        assert_eq!(row["page_properties"]["prop2"], "10");
+        assert_eq!(row["user_edgee_id"], "abc");
+        assert_eq!(row["client_country_code"], "FR");
+        assert_eq!(row["client_screen_width"], 1024);
+        assert_eq!(row["session_id"], "random");
+        assert_eq!(row["session_count"], 2);
+
+        // the schema doesn't depend on the event type
+        let page_event = sample_page_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("output_format".to_string(), "flat_json".to_string()),
+        ];
+        let page_row: serde_json::Value =
+            serde_json::from_str(&Component::page(page_event, settings).unwrap().body).unwrap();
+        assert_eq!(page_row["track_name"], serde_json::Value::Null);
+        assert_eq!(
+            page_row.as_object().unwrap().keys().collect::<Vec<_>>(),
+            row.as_object().unwrap().keys().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn page_with_csv_output() {
+        let mut event = sample_page_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+        event.context.page.title = "Hello, \"world\"".to_string();
+
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("output_format".to_string(), "csv".to_string()),
+            (
+                "csv_columns".to_string(),
+                "uuid, timestamp,event_type,context.page.title,context.client.country_code,data.properties.currency"
+                    .to_string(),
+            ),
+        ];
+        let result = Component::page(event.clone(), settings);
+
+        assert_eq!(result.is_err(), false);
+        let edgee_request = result.unwrap();
+        assert_eq!(edgee_request.url.ends_with(".csv"), true);
+        assert_eq!(
+            edgee_request.body,
+            format!(
+                "uuid,timestamp,event_type,context.page.title,context.client.country_code,data.properties.currency\n\
+                 {},123,page,\"Hello, \"\"world\"\"\",FR,USD\n",
+                event.uuid
+            )
+        );
+
+        // tab-separated values
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("output_format".to_string(), "csv".to_string()),
+            (
+                "csv_columns".to_string(),
+                "event_type,context.page.title".to_string(),
+            ),
+            ("csv_delimiter".to_string(), "tab".to_string()),
+        ];
+        let edgee_request = Component::page(event.clone(), settings).unwrap();
+        assert_eq!(edgee_request.url.ends_with(".tsv"), true);
+        assert_eq!(
+            edgee_request.body,
+            "event_type\tcontext.page.title\npage\t\"Hello, \"\"world\"\"\"\n"
+        );
+
+        // unknown columns are rejected
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("output_format".to_string(), "csv".to_string()),
+            (
+                "csv_columns".to_string(),
+                "uuid,context.page.nope".to_string(),
+            ),
+        ];
+        let result = Component::page(event, settings);
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .contains("Unknown CSV column: context.page.nope"),
+            true
+        );
+    }
+
+    #[test]
+    fn page_with_server_side_encryption() {
+        let event = sample_page_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+
+        // SSE-KMS with a customer managed key and a bucket key
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            (
+                "s3_server_side_encryption".to_string(),
+                "aws:kms".to_string(),
+            ),
+            (
+                "s3_sse_kms_key_id".to_string(),
+                "arn:aws:kms:eu-west-1:123456789012:key/test".to_string(),
+            ),
+            ("s3_sse_bucket_key_enabled".to_string(), "true".to_string()),
+        ];
+        let result = Component::page(event.clone(), settings);
+
+        assert_eq!(result.is_err(), false);
+        let edgee_request = result.unwrap();
+        assert_eq!(edgee_request.headers.len(), 8);
+        assert_eq!(
+            edgee_request
+                .headers
+                .iter()
+                .any(|(key, value)| key == "x-amz-server-side-encryption" && value == "aws:kms"),
+            true
+        );
+        assert_eq!(
+            edgee_request.headers.iter().any(|(key, value)| key
+                == "x-amz-server-side-encryption-aws-kms-key-id"
+                && value == "arn:aws:kms:eu-west-1:123456789012:key/test"),
+            true
+        );
+        assert_eq!(
+            edgee_request.headers.iter().any(|(key, value)| key == "authorization"
+                && value.contains("x-amz-server-side-encryption;x-amz-server-side-encryption-aws-kms-key-id;x-amz-server-side-encryption-bucket-key-enabled")),
+            true
+        );
+
+        // SSE-C, the key MD5 is computed by the component
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            (
+                "s3_sse_customer_key".to_string(),
+                "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=".to_string(),
+            ),
+        ];
+        let result = Component::page(event.clone(), settings);
+
+        assert_eq!(result.is_err(), false);
+        let edgee_request = result.unwrap();
+        assert_eq!(
+            edgee_request.headers.iter().any(|(key, value)| key
+                == "x-amz-server-side-encryption-customer-algorithm"
+                && value == "AES256"),
+            true
+        );
+        assert_eq!(
+            edgee_request.headers.iter().any(|(key, value)| key
+                == "x-amz-server-side-encryption-customer-key-md5"
+                && value == "tP/LI3N87DFaSk0aoqYgzg=="),
+            true
+        );
+
+        for (settings, error) in [
+            (
+                vec![("s3_server_side_encryption", "aws:nope")],
+                "Invalid server-side encryption",
+            ),
+            (
+                vec![("s3_sse_customer_key", "dG9vIHNob3J0")],
+                "expected a 256-bit key",
+            ),
+            (
+                vec![
+                    ("s3_server_side_encryption", "AES256"),
+                    ("s3_sse_kms_key_id", "key"),
+                ],
+                "KMS settings require",
+            ),
+            (
+                vec![
+                    (
+                        "s3_sse_customer_key",
+                        "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=",
+                    ),
+                    ("s3_sse_kms_key_id", "key"),
+                ],
+                "KMS settings require",
+            ),
+            (
+                vec![
+                    (
+                        "s3_sse_customer_key",
+                        "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=",
+                    ),
+                    ("s3_sse_bucket_key_enabled", "true"),
+                ],
+                "KMS settings require",
+            ),
+        ] {
+            let mut settings: Vec<(String, String)> = settings
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            settings.extend(vec![
+                ("aws_access_key".to_string(), "TEST".to_string()),
+                ("aws_secret_key".to_string(), "TEST".to_string()),
+                ("aws_region".to_string(), "eu-west-1".to_string()),
+                ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ]);
+            let result = Component::page(event.clone(), settings);
+            assert_eq!(result.is_err(), true);
+            assert_eq!(result.err().unwrap().contains(error), true);
+        }
+    }
+
+    #[test]
+    fn storage_class_per_event_type() {
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("s3_storage_class".to_string(), "STANDARD".to_string()),
+            (
+                "page_s3_storage_class".to_string(),
+                "INTELLIGENT_TIERING".to_string(),
+            ),
+        ];
+        let storage_class = |edgee_request: &EdgeeRequest| {
+            edgee_request
+                .headers
+                .iter()
+                .find(|(key, _)| key == "x-amz-storage-class")
+                .map(|(_, value)| value.clone())
+        };
+
+        let page_event = sample_page_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+        let edgee_request = Component::page(page_event.clone(), settings.clone()).unwrap();
+        assert_eq!(
+            storage_class(&edgee_request),
+            Some("INTELLIGENT_TIERING".to_string())
+        );
+        assert_eq!(
+            edgee_request
+                .headers
+                .iter()
+                .any(|(key, value)| key == "authorization" && value.contains("x-amz-storage-class")),
+            true
+        );
+
+        let user_event = sample_user_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+        let edgee_request = Component::user(user_event, settings).unwrap();
+        assert_eq!(storage_class(&edgee_request), Some("STANDARD".to_string()));
+
+        // unknown storage classes are rejected
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("page_s3_storage_class".to_string(), "CHEAPEST".to_string()),
+        ];
+        let result = Component::page(page_event, settings);
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .contains("Invalid S3 storage class for page_s3_storage_class: CHEAPEST"),
+            true
+        );
+    }
+
+    #[test]
+    fn track_with_object_tags() {
+        let event = sample_track_event(
+            "Add to cart".to_string(),
+            Some(Consent::Denied),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("s3_tags".to_string(), "team=data analytics".to_string()),
+            (
+                "s3_event_tags".to_string(),
+                "event_type,consent,country_code,track_name".to_string(),
+            ),
+        ];
+        let edgee_request = Component::track(event.clone(), settings).unwrap();
+        assert_eq!(
+            edgee_request
+                .headers
+                .iter()
+                .find(|(key, _)| key == "x-amz-tagging")
+                .map(|(_, value)| value.as_str()),
+            Some(
+                "team=data%20analytics&event_type=track&consent=denied&country_code=FR&track_name=Add%20to%20cart"
+            )
+        );
+        assert_eq!(
+            edgee_request
+                .headers
+                .iter()
+                .any(|(key, value)| key == "authorization" && value.contains("x-amz-tagging")),
+            true
+        );
+
+        // S3 tagging limits are checked
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            (
+                "s3_tags".to_string(),
+                "a=1,b=2,c=3,d=4,e=5,f=6,g=7,h=8".to_string(),
+            ),
+            (
+                "s3_event_tags".to_string(),
+                "event_type,consent,track_name".to_string(),
+            ),
+        ];
+        let result = Component::track(event, settings);
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .contains("Too many S3 tags: 11 (at most 10)"),
+            true
+        );
+    }
+
+    #[test]
+    fn page_with_object_metadata() {
+        let event = sample_page_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("s3_metadata".to_string(), "Source=edgee".to_string()),
+            (
+                "s3_event_metadata".to_string(),
+                "event-type=event_type,edgee-id=context.user.edgee_id,component-version=component_version,title=data.title,user-id=data.user_id".to_string(),
+            ),
+        ];
+        let edgee_request = Component::page(event.clone(), settings).unwrap();
+        let metadata: Vec<(String, String)> = edgee_request
+            .headers
+            .iter()
+            .filter(|(key, _)| key.starts_with("x-amz-meta-"))
+            .cloned()
+            .collect();
+        assert_eq!(
+            metadata,
+            vec![
+                ("x-amz-meta-source".to_string(), "edgee".to_string()),
+                ("x-amz-meta-event-type".to_string(), "page".to_string()),
+                ("x-amz-meta-edgee-id".to_string(), "abc".to_string()),
+                (
+                    "x-amz-meta-component-version".to_string(),
+                    env!("CARGO_PKG_VERSION").to_string()
+                ),
+                ("x-amz-meta-title".to_string(), "page title".to_string()),
+                // data.user_id doesn't exist on page events
+            ]
+        );
+        assert_eq!(
+            edgee_request
+                .headers
+                .iter()
+                .any(|(key, value)| key == "authorization"
+                    && value.contains("x-amz-meta-component-version;x-amz-meta-edgee-id")),
+            true
+        );
+
+        // only event fields can be used
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            (
+                "s3_event_metadata".to_string(),
+                "edgee-id=context.user.id".to_string(),
+            ),
+        ];
+        let result = Component::page(event, settings);
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .contains("Unknown event field for S3 metadata edgee-id: context.user.id"),
+            true
+        );
+    }
+
+    #[test]
+    fn page_with_content_headers() {
+        let event = sample_page_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+        let header = |edgee_request: &EdgeeRequest, name: &str| {
+            edgee_request
+                .headers
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        };
+
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+        ];
+        let edgee_request = Component::page(event.clone(), settings).unwrap();
+        assert_eq!(
+            header(&edgee_request, "content-type"),
+            Some("application/json".to_string())
+        );
+        assert_eq!(header(&edgee_request, "content-disposition"), None);
+        assert_eq!(header(&edgee_request, "cache-control"), None);
+
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("output_format".to_string(), "flat_json".to_string()),
+            (
+                "s3_content_disposition".to_string(),
+                "attachment".to_string(),
+            ),
+            ("s3_cache_control".to_string(), "no-store".to_string()),
+        ];
+        let edgee_request = Component::page(event.clone(), settings).unwrap();
+        assert_eq!(
+            header(&edgee_request, "content-type"),
+            Some("application/x-ndjson".to_string())
+        );
+        assert_eq!(
+            header(&edgee_request, "content-disposition"),
+            Some("attachment".to_string())
+        );
+        assert_eq!(
+            header(&edgee_request, "cache-control"),
+            Some("no-store".to_string())
+        );
+        assert_eq!(
+            header(&edgee_request, "authorization")
+                .unwrap()
+                .contains("SignedHeaders=cache-control;content-disposition;content-type;host;"),
+            true
+        );
+
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("output_format".to_string(), "csv".to_string()),
+        ];
+        let edgee_request = Component::page(event.clone(), settings).unwrap();
+        assert_eq!(
+            header(&edgee_request, "content-type"),
+            Some("text/csv".to_string())
+        );
+
+        // header values must be printable US-ASCII
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            (
+                "s3_content_disposition".to_string(),
+                "attachment; filename=\"événement.json\"".to_string(),
+            ),
+        ];
+        let result = Component::page(event, settings);
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .contains("Invalid header value for s3_content_disposition"),
+            true
+        );
+    }
+
+    #[test]
+    fn page_with_checksum() {
+        let event = sample_page_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("s3_checksum_algorithm".to_string(), "CRC32C".to_string()),
+            ("s3_content_md5".to_string(), "true".to_string()),
+        ];
+        let edgee_request = Component::page(event, settings).unwrap();
+
+        let header_names: Vec<&str> = edgee_request
+            .headers
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect();
+        assert_eq!(header_names.contains(&"content-md5"), true);
+        assert_eq!(header_names.contains(&"x-amz-checksum-crc32c"), true);
+        assert_eq!(
+            edgee_request
+                .headers
+                .iter()
+                .any(|(key, value)| key == "x-amz-sdk-checksum-algorithm" && value == "CRC32C"),
+            true
+        );
+        assert_eq!(
+            edgee_request
+                .headers
+                .iter()
+                .any(|(key, value)| key == "authorization"
+                    && value.contains("content-md5;")
+                    && value.contains("x-amz-checksum-crc32c;")
+                    && value.contains("x-amz-sdk-checksum-algorithm")),
+            true
+        );
+    }
+
+    #[test]
+    fn user_with_consent_policy() {
+        let settings_with = |policy: &str| {
+            vec![
+                ("aws_access_key".to_string(), "TEST".to_string()),
+                ("aws_secret_key".to_string(), "TEST".to_string()),
+                ("aws_region".to_string(), "eu-west-1".to_string()),
+                ("s3_bucket".to_string(), "test-bucket".to_string()),
+                ("s3_key_prefix".to_string(), "events/".to_string()),
+                ("consent_policy".to_string(), policy.to_string()),
+                (
+                    "consent_denied_s3_bucket".to_string(),
+                    "denied-bucket".to_string(),
+                ),
+                (
+                    "consent_pending_s3_key_prefix".to_string(),
+                    "pending/".to_string(),
+                ),
+            ]
+        };
+        let event_with = |consent: Option<Consent>| {
+            sample_user_event(consent, "abc".to_string(), "fr".to_string(), true)
+        };
+
+        // drop
+        let mut settings = settings_with("drop");
+        settings.retain(|(key, _)| !key.starts_with("consent_") || key == "consent_policy");
+        let result = Component::user(event_with(Some(Consent::Denied)), settings.clone());
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .contains("Event dropped by the consent policy (consent is denied)"),
+            true
+        );
+        assert_eq!(
+            Component::user(event_with(Some(Consent::Pending)), settings.clone()).is_err(),
+            true
+        );
+        assert_eq!(
+            Component::user(event_with(Some(Consent::Granted)), settings.clone()).is_err(),
+            false
+        );
+        assert_eq!(Component::user(event_with(None), settings).is_err(), false);
+
+        // anonymize
+        let mut settings = settings_with("anonymize");
+        settings.retain(|(key, _)| !key.starts_with("consent_") || key == "consent_policy");
+        let edgee_request =
+            Component::user(event_with(Some(Consent::Pending)), settings.clone()).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&edgee_request.body).unwrap();
+        assert_eq!(body["context"]["client"]["ip"], "");
+        assert_eq!(body["context"]["client"]["user_agent"], "");
+        assert_eq!(body["context"]["client"]["country_code"], "FR");
+        assert_eq!(body["context"]["user"]["user_id"], "");
+        assert_eq!(body["context"]["user"]["edgee_id"], "");
+        assert_eq!(body["data"]["User"]["anonymous_id"], "");
+        let edgee_request = Component::user(event_with(Some(Consent::Granted)), settings).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&edgee_request.body).unwrap();
+        assert_eq!(body["context"]["client"]["ip"], "192.168.0.1");
+
+        // route
+        let settings = settings_with("route");
+        let edgee_request =
+            Component::user(event_with(Some(Consent::Denied)), settings.clone()).unwrap();
+        assert_eq!(
+            edgee_request
+                .url
+                .starts_with("https://denied-bucket.s3.eu-west-1.amazonaws.com/events/"),
+            true
+        );
+        let edgee_request =
+            Component::user(event_with(Some(Consent::Pending)), settings.clone()).unwrap();
+        assert_eq!(
+            edgee_request
+                .url
+                .starts_with("https://test-bucket.s3.eu-west-1.amazonaws.com/pending/"),
+            true
+        );
+        let edgee_request = Component::user(event_with(Some(Consent::Granted)), settings).unwrap();
+        assert_eq!(
+            edgee_request
+                .url
+                .starts_with("https://test-bucket.s3.eu-west-1.amazonaws.com/events/"),
+            true
+        );
+
+        // destinations only make sense when routing
+        let result = Component::user(event_with(Some(Consent::Denied)), settings_with("drop"));
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .contains("Consent destinations require consent_policy = route"),
+            true
+        );
+
+        // destinations are checked like the default ones
+        for (key, value, error) in [
+            (
+                "consent_denied_s3_bucket",
+                "Denied_Bucket",
+                "Invalid S3 bucket name for consent_denied_s3_bucket: Denied_Bucket",
+            ),
+            (
+                "consent_pending_s3_key_prefix",
+                "my pending/",
+                "Invalid character ' ' in S3 key prefix for consent_pending_s3_key_prefix: my pending/",
+            ),
+        ] {
+            let mut settings = settings_with("route");
+            settings.retain(|(setting, _)| setting != key);
+            settings.push((key.to_string(), value.to_string()));
+            let result = Component::user(event_with(Some(Consent::Denied)), settings);
+            assert_eq!(result.is_err(), true);
+            assert_eq!(result.err().unwrap().contains(error), true);
+        }
+    }
+
+    #[test]
+    fn user_with_ip_anonymization() {
+        let settings_with = |anonymization: &str| {
+            vec![
+                ("aws_access_key".to_string(), "TEST".to_string()),
+                ("aws_secret_key".to_string(), "TEST".to_string()),
+                ("aws_region".to_string(), "eu-west-1".to_string()),
+                ("s3_bucket".to_string(), "test-bucket".to_string()),
+                ("s3_key_prefix".to_string(), "events/".to_string()),
+                ("ip_anonymization".to_string(), anonymization.to_string()),
+            ]
+        };
+        let event = sample_user_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+        let client_ip = |settings: Vec<(String, String)>| {
+            let edgee_request = Component::user(event.clone(), settings).unwrap();
+            let body: serde_json::Value = serde_json::from_str(&edgee_request.body).unwrap();
+            body["context"]["client"]["ip"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(client_ip(settings_with("none")), "192.168.0.1");
+        assert_eq!(client_ip(settings_with("truncate")), "192.168.0.0");
+        assert_eq!(client_ip(settings_with("drop")), "");
+
+        let mut settings = settings_with("hash");
+        settings.push(("ip_hash_salt".to_string(), "secret".to_string()));
+        assert_eq!(
+            client_ip(settings),
+            "89038a521f07de6655d20935914b5c9aa78983bcb1ba44d1b1e8af5fc37a8ec7"
+        );
+
+        // IPv6 and IPv4-mapped IPv6 addresses
+        let mut ipv6_event = event.clone();
+        ipv6_event.context.client.ip = "2001:db8:85a3:8d3:1319:8a2e:370:7348".to_string();
+        let edgee_request = Component::user(ipv6_event, settings_with("truncate")).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&edgee_request.body).unwrap();
+        assert_eq!(body["context"]["client"]["ip"], "2001:db8:85a3::");
+        let mut mapped_event = event.clone();
+        mapped_event.context.client.ip = "::ffff:192.168.0.1".to_string();
+        let edgee_request = Component::user(mapped_event, settings_with("truncate")).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&edgee_request.body).unwrap();
+        assert_eq!(body["context"]["client"]["ip"], "::ffff:192.168.0.0");
+
+        // hashing requires a salt, and the salt requires hashing
+        let result = Component::user(event.clone(), settings_with("hash"));
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .contains("IP hashing requires a secret ip_hash_salt"),
+            true
+        );
+        let mut settings = settings_with("truncate");
+        settings.push(("ip_hash_salt".to_string(), "secret".to_string()));
+        assert_eq!(Component::user(event.clone(), settings).is_err(), true);
+        assert_eq!(Component::user(event, settings_with("mask")).is_err(), true);
+    }
+
+    #[test]
+    fn track_with_pseudonymized_fields() {
+        let settings_with = |fields: &str| {
+            vec![
+                ("aws_access_key".to_string(), "TEST".to_string()),
+                ("aws_secret_key".to_string(), "TEST".to_string()),
+                ("aws_region".to_string(), "eu-west-1".to_string()),
+                ("s3_bucket".to_string(), "test-bucket".to_string()),
+                ("s3_key_prefix".to_string(), "events/".to_string()),
+                ("pseudonymized_fields".to_string(), fields.to_string()),
+                ("pseudonymization_salt".to_string(), "secret".to_string()),
+            ]
+        };
+        let mut event = sample_track_event(
+            "purchase".to_string(),
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+        if let Data::Track(track) = &mut event.data {
+            track
+                .properties
+                .push(("email".to_string(), "jane@example.com".to_string()));
+        }
+        event.context.user.anonymous_id = "".to_string();
+
+        let edgee_request = Component::track(
+            event.clone(),
+            settings_with(
+                "context.user.user_id, context.user.anonymous_id,data.properties.email,data.properties.missing",
+            ),
+        )
+        .unwrap();
+        let body: serde_json::Value = serde_json::from_str(&edgee_request.body).unwrap();
+        assert_eq!(
+            body["context"]["user"]["user_id"],
+            "77de38e4b50e618a0ebb95db61e2f42697391659d82c064a5f81b9f48d85ccd5"
+        );
+        // empty values stay empty
+        assert_eq!(body["context"]["user"]["anonymous_id"], "");
+        assert_eq!(body["context"]["user"]["edgee_id"], "abc");
+        let properties = body["data"]["Track"]["properties"].as_array().unwrap();
+        assert_eq!(properties[0], serde_json::json!(["prop1", "value1"]));
+        assert_eq!(
+            properties[3],
+            serde_json::json!([
+                "email",
+                "fb817989d942e7ffb3d4b8b204f7abca29f4c25c3fa46574da84c50f30d07513"
+            ])
+        );
+
+        // the same value always gives the same hash
+        let edgee_request =
+            Component::track(event.clone(), settings_with("data.properties.prop1")).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&edgee_request.body).unwrap();
+        let first_hash = body["data"]["Track"]["properties"][0][1].clone();
+        let edgee_request =
+            Component::track(event.clone(), settings_with("data.properties.prop1")).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&edgee_request.body).unwrap();
+        assert_eq!(body["data"]["Track"]["properties"][0][1], first_hash);
+        assert_eq!(first_hash == "value1", false);
+
+        for (fields, error) in [
+            (
+                "context.user.nope",
+                "Invalid pseudonymized field: context.user.nope",
+            ),
+            ("timestamp", "Invalid pseudonymized field: timestamp"),
+            (
+                "context.client.screen_width",
+                "Invalid pseudonymized field: context.client.screen_width",
+            ),
+        ] {
+            let result = Component::track(event.clone(), settings_with(fields));
+            assert_eq!(result.is_err(), true);
+            assert_eq!(result.err().unwrap().contains(error), true);
+        }
+
+        // a salt is required
+        let mut settings = settings_with("context.user.user_id");
+        settings.retain(|(key, _)| key != "pseudonymization_salt");
+        let result = Component::track(event, settings);
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .contains("Pseudonymized fields require a secret pseudonymization_salt"),
+            true
+        );
+    }
+
+    #[test]
+    fn page_with_field_projection() {
+        let settings_with = |include: &str, exclude: &str| {
+            vec![
+                ("aws_access_key".to_string(), "TEST".to_string()),
+                ("aws_secret_key".to_string(), "TEST".to_string()),
+                ("aws_region".to_string(), "eu-west-1".to_string()),
+                ("s3_bucket".to_string(), "test-bucket".to_string()),
+                ("s3_key_prefix".to_string(), "events/".to_string()),
+                (
+                    "s3_key_template".to_string(),
+                    "{event_type}/{context.client.country_code}/{uuid}".to_string(),
+                ),
+                ("include_fields".to_string(), include.to_string()),
+                ("exclude_fields".to_string(), exclude.to_string()),
+            ]
+        };
+        let event = sample_page_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+
+        // exclude
+        let edgee_request = Component::page(
+            event.clone(),
+            settings_with(
+                "",
+                "context.campaign, context.client.user_agent_full_version_list,context.client.screen_density,data.properties.prop1",
+            ),
+        )
+        .unwrap();
+        let body: serde_json::Value = serde_json::from_str(&edgee_request.body).unwrap();
+        assert_eq!(body["context"]["campaign"]["name"], "");
+        assert_eq!(body["context"]["campaign"]["source"], "");
+        assert_eq!(
+            body["context"]["client"]["user_agent_full_version_list"],
+            ""
+        );
+        assert_eq!(body["context"]["client"]["screen_density"], 0.0);
+        assert_eq!(body["context"]["client"]["user_agent"], "Chrome");
+        assert_eq!(
+            body["data"]["Page"]["properties"],
+            serde_json::json!([["prop2", "10"], ["currency", "USD"]])
+        );
+        assert_eq!(
+            body["context"]["page"]["properties"]
+                .as_array()
+                .unwrap()
+                .len(),
+            3
+        );
+        assert_eq!(
+            edgee_request
+                .url
+                .starts_with("https://test-bucket.s3.eu-west-1.amazonaws.com/events/page/FR/"),
+            true
+        );
+
+        // the key, metadata, tags and routing rules don't see left out fields either
+        let mut settings = settings_with("", "context.client.country_code,context.user.edgee_id");
+        settings.extend(vec![
+            (
+                "s3_event_metadata".to_string(),
+                "edgee-id=context.user.edgee_id".to_string(),
+            ),
+            ("s3_event_tags".to_string(), "country_code".to_string()),
+            (
+                "routing_rules".to_string(),
+                "context.client.country_code in [FR] => bucket=events-fr".to_string(),
+            ),
+        ]);
+        let edgee_request = Component::page(event.clone(), settings).unwrap();
+        assert_eq!(
+            edgee_request
+                .url
+                .starts_with("https://test-bucket.s3.eu-west-1.amazonaws.com/events/page/unknown/"),
+            true
+        );
+        assert_eq!(
+            edgee_request
+                .headers
+                .iter()
+                .any(|(key, value)| key == "x-amz-meta-edgee-id" && value.is_empty()),
+            true
+        );
+        assert_eq!(
+            edgee_request
+                .headers
+                .iter()
+                .any(|(key, value)| key == "x-amz-tagging" && value == "country_code="),
+            true
+        );
+
+        // include, then exclude
+        let edgee_request = Component::page(
+            event.clone(),
+            settings_with(
+                "uuid,timestamp,context.client,data.url,context.page.properties.prop2",
+                "context.client.ip",
+            ),
+        )
+        .unwrap();
+        let body: serde_json::Value = serde_json::from_str(&edgee_request.body).unwrap();
+        assert_eq!(body["uuid"], event.uuid.as_str());
+        assert_eq!(body["timestamp"], 123);
+        assert_eq!(body["timestamp_millis"], 0);
+        assert_eq!(body["event_type"], "Page");
+        assert_eq!(body["consent"], serde_json::Value::Null);
+        assert_eq!(body["context"]["client"]["country_code"], "FR");
+        assert_eq!(body["context"]["client"]["screen_width"], 1024);
+        assert_eq!(body["context"]["client"]["ip"], "");
+        assert_eq!(body["data"]["Page"]["url"], event.context.page.url.as_str());
+        assert_eq!(body["data"]["Page"]["title"], "");
+        assert_eq!(body["data"]["Page"]["keywords"], serde_json::json!([]));
+        assert_eq!(body["data"]["Page"]["properties"], serde_json::json!([]));
+        assert_eq!(
+            body["context"]["page"]["properties"],
+            serde_json::json!([["prop2", "10"]])
+        );
+        assert_eq!(body["context"]["user"]["edgee_id"], "");
+        assert_eq!(body["context"]["session"]["session_start"], false);
+
+        for (include, exclude, error) in [
+            (
+                "context.client.nope",
+                "",
+                "Unknown included field: context.client.nope",
+            ),
+            ("", "campaign", "Unknown excluded field: campaign"),
+            ("", "event_type", "event_type can't be excluded"),
+        ] {
+            let result = Component::page(event.clone(), settings_with(include, exclude));
+            assert_eq!(result.is_err(), true);
+            assert_eq!(result.err().unwrap().contains(error), true);
+        }
+    }
+
+    #[test]
+    fn events_with_event_type_settings() {
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("s3_key_prefix".to_string(), "events/".to_string()),
+            ("edgee_page_event_enabled".to_string(), "true".to_string()),
+            ("edgee_user_event_enabled".to_string(), "false".to_string()),
+            ("track_s3_bucket".to_string(), "track-bucket".to_string()),
+            ("track_s3_key_prefix".to_string(), "tracks/".to_string()),
+            ("track_output_format".to_string(), "csv".to_string()),
+            (
+                "track_s3_storage_class".to_string(),
+                "GLACIER_IR".to_string(),
+            ),
+        ];
+
+        let page_request = Component::page(
+            sample_page_event(
+                Some(Consent::Granted),
+                "abc".to_string(),
+                "fr".to_string(),
+                true,
+            ),
+            settings.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            page_request
+                .url
+                .starts_with("https://test-bucket.s3.eu-west-1.amazonaws.com/events/"),
+            true
+        );
+        assert_eq!(page_request.url.ends_with(".json"), true);
+
+        let track_request = Component::track(
+            sample_track_event(
+                "purchase".to_string(),
+                Some(Consent::Granted),
+                "abc".to_string(),
+                "fr".to_string(),
+                true,
+            ),
+            settings.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            track_request
+                .url
+                .starts_with("https://track-bucket.s3.eu-west-1.amazonaws.com/tracks/"),
+            true
+        );
+        assert_eq!(track_request.url.ends_with(".csv"), true);
+        assert_eq!(
+            track_request.body.starts_with("uuid,timestamp,event_type"),
+            true
+        );
+        let header = |name: &str| {
+            track_request
+                .headers
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(header("content-type"), Some("text/csv".to_string()));
+        assert_eq!(
+            header("x-amz-storage-class"),
+            Some("GLACIER_IR".to_string())
+        );
+        assert_eq!(
+            header("host"),
+            Some("track-bucket.s3.eu-west-1.amazonaws.com".to_string())
+        );
+
+        let result = Component::user(
+            sample_user_event(
+                Some(Consent::Granted),
+                "abc".to_string(),
+                "fr".to_string(),
+                true,
+            ),
+            settings.clone(),
+        );
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .contains("Events of type user are disabled (edgee_user_event_enabled = false)"),
+            true
+        );
+
+        for (key, value, error) in [
+            (
+                "edgee_track_event_enabled",
+                "no",
+                "Invalid boolean value for edgee_track_event_enabled",
+            ),
+            ("page_output_format", "xml", "Invalid output format: xml"),
+            (
+                "user_s3_bucket",
+                "user.bucket.",
+                "Invalid S3 bucket name for user_s3_bucket: user.bucket.",
+            ),
+            (
+                "page_s3_key_prefix",
+                "pages?/",
+                "Invalid character '?' in S3 key prefix for page_s3_key_prefix: pages?/",
+            ),
+        ] {
+            let mut settings = settings.clone();
+            settings.push((key.to_string(), value.to_string()));
+            let result = Component::user(
+                sample_user_event(
+                    Some(Consent::Granted),
+                    "abc".to_string(),
+                    "fr".to_string(),
+                    true,
+                ),
+                settings,
+            );
+            assert_eq!(result.is_err(), true);
+            assert_eq!(result.err().unwrap().contains(error), true);
+        }
+    }
+
+    #[test]
+    fn track_with_name_rules() {
+        let settings = vec![
+            ("aws_access_key".to_string(), "TEST".to_string()),
+            ("aws_secret_key".to_string(), "TEST".to_string()),
+            ("aws_region".to_string(), "eu-west-1".to_string()),
+            ("s3_bucket".to_string(), "test-bucket".to_string()),
+            ("s3_key_prefix".to_string(), "events/".to_string()),
+            (
+                "track_name_allowlist".to_string(),
+                "checkout_*, purchase, signup*".to_string(),
+            ),
+            (
+                "track_name_denylist".to_string(),
+                "*_test,*debug*".to_string(),
+            ),
+            (
+                "track_name_s3_key_prefixes".to_string(),
+                "purchase=orders/,checkout_*=checkout/".to_string(),
+            ),
+        ];
+        let track = |name: &str, settings: Vec<(String, String)>| {
+            Component::track(
+                sample_track_event(
+                    name.to_string(),
+                    Some(Consent::Granted),
+                    "abc".to_string(),
+                    "fr".to_string(),
+                    true,
+                ),
+                settings,
+            )
+        };
+
+        for (name, url_prefix) in [
+            (
+                "purchase",
+                "https://test-bucket.s3.eu-west-1.amazonaws.com/orders/",
+            ),
+            (
+                "checkout_started",
+                "https://test-bucket.s3.eu-west-1.amazonaws.com/checkout/",
+            ),
+            (
+                "signup",
+                "https://test-bucket.s3.eu-west-1.amazonaws.com/events/",
+            ),
+        ] {
+            let edgee_request = track(name, settings.clone()).unwrap();
+            assert_eq!(edgee_request.url.starts_with(url_prefix), true, "{name}");
+        }
+
+        for (name, error) in [
+            (
+                "page_scrolled",
+                "Track event page_scrolled filtered out (not in track_name_allowlist)",
+            ),
+            (
+                "signup_test",
+                "Track event signup_test filtered out (matches *_test in track_name_denylist)",
+            ),
+            (
+                "checkout_debug",
+                "Track event checkout_debug filtered out (matches *debug* in track_name_denylist)",
+            ),
+        ] {
+            let result = track(name, settings.clone());
+            assert_eq!(result.is_err(), true);
+            assert_eq!(result.err().unwrap().contains(error), true);
+        }
+
+        // other event types aren't filtered
+        let edgee_request = Component::page(
+            sample_page_event(
+                Some(Consent::Granted),
+                "abc".to_string(),
+                "fr".to_string(),
+                true,
+            ),
+            settings.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            edgee_request
+                .url
+                .starts_with("https://test-bucket.s3.eu-west-1.amazonaws.com/events/"),
+            true
+        );
+
+        for (key_prefixes, error) in [
+            (
+                "purchase:orders/",
+                "Invalid track name key prefix: purchase:orders/ (expected pattern=prefix)",
+            ),
+            (
+                "purchase=my orders/",
+                "Invalid character ' ' in S3 key prefix for track_name_s3_key_prefixes: my orders/",
+            ),
+        ] {
+            let mut invalid_settings = settings.clone();
+            invalid_settings.push((
+                "track_name_s3_key_prefixes".to_string(),
+                key_prefixes.to_string(),
+            ));
+            let result = track("purchase", invalid_settings);
+            assert_eq!(result.is_err(), true);
+            assert_eq!(result.err().unwrap().contains(error), true);
+        }
+    }
+
+    #[test]
+    fn page_with_routing_rules() {
+        let settings_with = |rules: &str| {
+            vec![
+                ("aws_access_key".to_string(), "TEST".to_string()),
+                ("aws_secret_key".to_string(), "TEST".to_string()),
+                ("aws_region".to_string(), "us-east-1".to_string()),
+                ("s3_bucket".to_string(), "events-default".to_string()),
+                ("s3_key_prefix".to_string(), "events/".to_string()),
+                ("routing_rules".to_string(), rules.to_string()),
+            ]
+        };
+        let settings = settings_with(
+            "context.client.country_code in [DE, FR] => bucket=events-eu, region=eu-west-1, prefix=eu/;
+             context.page.path startswith /admin => prefix=admin/, storage_class=GLACIER_IR;
+             consent == \"granted\" => prefix=granted/",
+        );
+        let event_with = |country_code: &str, path: &str, consent: Option<Consent>| {
+            let mut event = sample_page_event(consent, "abc".to_string(), "fr".to_string(), true);
+            event.context.client.country_code = country_code.to_string();
+            event.context.page.path = path.to_string();
+            event
+        };
+        let header = |edgee_request: &EdgeeRequest, name: &str| {
+            edgee_request
+                .headers
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        };
+
+        // the first matching rule wins, with its own region for the host and the signature
+        let edgee_request = Component::page(
+            event_with("FR", "/admin", Some(Consent::Granted)),
+            settings.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            edgee_request
+                .url
+                .starts_with("https://events-eu.s3.eu-west-1.amazonaws.com/eu/"),
+            true
+        );
+        assert_eq!(
+            header(&edgee_request, "authorization")
+                .unwrap()
+                .contains("/eu-west-1/s3/aws4_request"),
+            true
+        );
+        assert_eq!(header(&edgee_request, "x-amz-storage-class"), None);
+
+        let edgee_request = Component::page(
+            event_with("US", "/admin/users", Some(Consent::Granted)),
+            settings.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            edgee_request
+                .url
+                .starts_with("https://events-default.s3.us-east-1.amazonaws.com/admin/"),
+            true
+        );
+        assert_eq!(
+            header(&edgee_request, "x-amz-storage-class"),
+            Some("GLACIER_IR".to_string())
+        );
+
+        let edgee_request = Component::page(
+            event_with("US", "/", Some(Consent::Granted)),
+            settings.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            edgee_request
+                .url
+                .starts_with("https://events-default.s3.us-east-1.amazonaws.com/granted/"),
+            true
+        );
+
+        // events matching no rule keep the default destination
+        let edgee_request = Component::page(event_with("US", "/", None), settings.clone()).unwrap();
+        assert_eq!(
+            edgee_request
+                .url
+                .starts_with("https://events-default.s3.us-east-1.amazonaws.com/events/"),
+            true
+        );
+        assert_eq!(
+            header(&edgee_request, "authorization")
+                .unwrap()
+                .contains("/us-east-1/s3/aws4_request"),
+            true
+        );
+
+        for (rules, error) in [
+            (
+                "consent == granted",
+                "Invalid routing rule: consent == granted",
+            ),
+            (
+                "context.client.nope == FR => prefix=fr/",
+                "Unknown routing condition field: context.client.nope",
+            ),
+            (
+                "consent ~= granted => prefix=fr/",
+                "Unknown routing condition operator: ~=",
+            ),
+            (
+                "consent in granted => prefix=fr/",
+                "Invalid routing condition list: granted",
+            ),
+            (
+                "consent == granted => path=fr/",
+                "Unknown routing destination: path",
+            ),
+            (
+                "consent == granted => storage_class=COLD",
+                "Invalid S3 storage class for routing_rules: COLD",
+            ),
+            (
+                "consent == granted => bucket=Events_EU",
+                "Invalid S3 bucket name for routing_rules: Events_EU",
+            ),
+            (
+                "consent == granted => prefix=my events/",
+                "Invalid character ' ' in S3 key prefix for routing_rules: my events/",
+            ),
+            ("consent == granted => ", "Empty routing destination"),
+        ] {
+            let result = Component::page(event_with("FR", "/", None), settings_with(rules));
+            assert_eq!(result.is_err(), true, "{rules}");
+            assert_eq!(result.err().unwrap().contains(error), true, "{rules}");
+        }
+    }
+
+    #[test]
+    fn page_with_data_residency() {
+        let settings_with = |data_residency: &str| {
+            vec![
+                ("aws_access_key".to_string(), "TEST".to_string()),
+                ("aws_secret_key".to_string(), "TEST".to_string()),
+                ("aws_region".to_string(), "us-east-1".to_string()),
+                ("s3_bucket".to_string(), "events-default".to_string()),
+                ("s3_key_prefix".to_string(), "events/".to_string()),
+                ("data_residency".to_string(), data_residency.to_string()),
+            ]
+        };
+        let settings = settings_with(
+            "Europe=eu-west-1/events-eu, North America=us-east-1/events-us, South America=sa-east-1/events-sa,
+             Asia=ap-southeast-1/events-apac, Oceania=ap-southeast-2/events-oceania, africa=af-south-1/events-africa,
+             CH=eu-central-2/events-ch",
+        );
+
+        for (continent, country_code, region, bucket) in [
+            ("Europe", "FR", "eu-west-1", "events-eu"),
+            ("North America", "US", "us-east-1", "events-us"),
+            ("South America", "BR", "sa-east-1", "events-sa"),
+            ("Asia", "JP", "ap-southeast-1", "events-apac"),
+            ("Oceania", "AU", "ap-southeast-2", "events-oceania"),
+            ("Africa", "ZA", "af-south-1", "events-africa"),
+            // continents may also be given by their code
+            ("EU", "DE", "eu-west-1", "events-eu"),
+            ("NA", "CA", "us-east-1", "events-us"),
+            ("SA", "AR", "sa-east-1", "events-sa"),
+            ("AS", "SG", "ap-southeast-1", "events-apac"),
+            ("OC", "NZ", "ap-southeast-2", "events-oceania"),
+            ("AF", "KE", "af-south-1", "events-africa"),
+            // countries win over continents
+            ("Europe", "CH", "eu-central-2", "events-ch"),
+            ("EU", "CH", "eu-central-2", "events-ch"),
+            // unmapped or unknown locations fall back to the default destination
+            ("Antarctica", "AQ", "us-east-1", "events-default"),
+            ("AN", "AQ", "us-east-1", "events-default"),
+            ("Atlantis", "", "us-east-1", "events-default"),
+            ("", "", "us-east-1", "events-default"),
+        ] {
+            let mut event = sample_page_event(
+                Some(Consent::Granted),
+                "abc".to_string(),
+                "fr".to_string(),
+                true,
+            );
+            event.context.client.continent = continent.to_string();
+            event.context.client.country_code = country_code.to_string();
+
+            let edgee_request = Component::page(event, settings.clone()).unwrap();
+            assert_eq!(
+                edgee_request.url.starts_with(&format!(
+                    "https://{bucket}.s3.{region}.amazonaws.com/events/"
+                )),
+                true,
+                "{continent} {country_code}"
+            );
+            assert_eq!(
+                edgee_request
+                    .headers
+                    .iter()
+                    .any(|(key, value)| key == "authorization"
+                        && value.contains(&format!("/{region}/s3/aws4_request"))),
+                true,
+                "{continent} {country_code}"
+            );
+        }
+
+        for (data_residency, error) in [
+            (
+                "Europe=eu-west-1",
+                "Invalid data residency: Europe=eu-west-1",
+            ),
+            (
+                "Europa=eu-west-1/events-eu",
+                "Invalid data residency location: Europa",
+            ),
+            (
+                "Europe=EU_WEST/events-eu",
+                "Invalid data residency region for Europe: EU_WEST",
+            ),
+            (
+                "Europe=eu-west-1/",
+                "Missing data residency bucket for Europe",
+            ),
+            (
+                "Europe=eu-west-1/events_eu",
+                "Invalid S3 bucket name for data_residency: events_eu",
+            ),
+            (
+                "fr=eu-west-3/events-fr,FR=eu-west-1/events-eu",
+                "Duplicate data residency location: FR",
+            ),
+            // continent codes would be mistaken for country codes
+            (
+                "EU=eu-west-1/events-eu",
+                "Invalid data residency location: EU (continent codes are ambiguous, use Europe instead)",
+            ),
+            (
+                "NA=us-east-1/events-us",
+                "Invalid data residency location: NA (continent codes are ambiguous, use North America instead)",
+            ),
+            (
+                "SA=sa-east-1/events-sa",
+                "Invalid data residency location: SA (continent codes are ambiguous, use South America instead)",
+            ),
+            (
+                "af=af-south-1/events-africa",
+                "Invalid data residency location: af (continent codes are ambiguous, use Africa instead)",
+            ),
+            (
+                "AS=ap-southeast-1/events-apac",
+                "Invalid data residency location: AS (continent codes are ambiguous, use Asia instead)",
+            ),
+            (
+                "OC=ap-southeast-2/events-oceania",
+                "Invalid data residency location: OC (continent codes are ambiguous, use Oceania instead)",
+            ),
+            (
+                "AN=us-east-1/events-antarctica",
+                "Invalid data residency location: AN (continent codes are ambiguous, use Antarctica instead)",
+            ),
+        ] {
+            let result = Component::page(
+                sample_page_event(
+                    Some(Consent::Granted),
+                    "abc".to_string(),
+                    "fr".to_string(),
+                    true,
+                ),
+                settings_with(data_residency),
+            );
+            assert_eq!(result.is_err(), true, "{data_residency}");
+            assert_eq!(
+                result.err().unwrap().contains(error),
+                true,
+                "{data_residency}"
+            );
+        }
+
+        // consent routes keep the residency bucket, and only change the key prefix
+        let mut event = sample_page_event(
+            Some(Consent::Denied),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+        event.context.client.continent = "Europe".to_string();
+        event.context.client.country_code = "FR".to_string();
+        let mut settings = settings.clone();
+        settings.push(("consent_policy".to_string(), "route".to_string()));
+        settings.push((
+            "consent_denied_s3_key_prefix".to_string(),
+            "denied/".to_string(),
+        ));
+        let edgee_request = Component::page(event.clone(), settings.clone()).unwrap();
+        assert_eq!(
+            edgee_request
+                .url
+                .starts_with("https://events-eu.s3.eu-west-1.amazonaws.com/denied/"),
+            true
+        );
+
+        settings.push((
+            "consent_denied_s3_bucket".to_string(),
+            "events-denied".to_string(),
+        ));
+        let result = Component::page(event, settings);
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .contains("Consent route buckets can't be combined with data residency"),
+            true
+        );
+    }
+
+    #[test]
+    fn events_with_sampling() {
+        let settings_with = |sampling: &[(&str, &str)]| {
+            let mut settings = vec![
+                ("aws_access_key".to_string(), "TEST".to_string()),
+                ("aws_secret_key".to_string(), "TEST".to_string()),
+                ("aws_region".to_string(), "eu-west-1".to_string()),
+                ("s3_bucket".to_string(), "test-bucket".to_string()),
+                ("s3_key_prefix".to_string(), "events/".to_string()),
+            ];
+            settings.extend(
+                sampling
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string())),
+            );
+            settings
+        };
+        let page_event = |edgee_id: &str| {
+            sample_page_event(
+                Some(Consent::Granted),
+                edgee_id.to_string(),
+                "fr".to_string(),
+                true,
+            )
+        };
+        let track_event = |name: &str, edgee_id: &str| {
+            sample_track_event(
+                name.to_string(),
+                Some(Consent::Granted),
+                edgee_id.to_string(),
+                "fr".to_string(),
+                true,
+            )
+        };
+        let sample_rate = |edgee_request: &EdgeeRequest| {
+            edgee_request
+                .headers
+                .iter()
+                .find(|(key, _)| key == "x-amz-meta-sample-rate")
+                .map(|(_, value)| value.clone())
+        };
+
+        // the edgee_id "abc" hashes to ~0.73 and "user-5" to ~0.36
+        let settings = settings_with(&[("sample_rate", "0.5")]);
+        let result = Component::page(page_event("abc"), settings.clone());
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .contains("Event sampled out (sample rate 0.5)"),
+            true
+        );
+        let edgee_request = Component::page(page_event("user-5"), settings.clone()).unwrap();
+        assert_eq!(sample_rate(&edgee_request), Some("0.5".to_string()));
+
+        // the sample rate is stored next to the event fields, leaving its own sample_rate property as is
+        let mut event = page_event("user-5");
+        if let Data::Page(ref mut page) = event.data {
+            page.properties
+                .push(("sample_rate".to_string(), "custom".to_string()));
+        }
+        let edgee_request = Component::page(event.clone(), settings.clone()).unwrap();
+        let mut body: serde_json::Value = serde_json::from_str(&edgee_request.body).unwrap();
+        assert_eq!(
+            body.as_object_mut().unwrap().remove("sample_rate"),
+            Some(serde_json::json!(0.5))
+        );
+        assert_eq!(body, serde_json::to_value(&event).unwrap());
+        assert_eq!(sample_rate(&edgee_request), Some("0.5".to_string()));
+
+        // and in its own column with the other output formats
+        let mut flat_json_settings = settings.clone();
+        flat_json_settings.push(("output_format".to_string(), "flat_json".to_string()));
+        let edgee_request = Component::page(page_event("user-5"), flat_json_settings).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&edgee_request.body).unwrap();
+        assert_eq!(body["sample_rate"], 0.5);
+        let mut csv_settings = settings.clone();
+        csv_settings.push(("output_format".to_string(), "csv".to_string()));
+        let edgee_request = Component::page(page_event("user-5"), csv_settings.clone()).unwrap();
+        let lines: Vec<&str> = edgee_request.body.lines().collect();
+        assert_eq!(
+            lines[0].ends_with(",context.session.session_id,sample_rate"),
+            true
+        );
+        assert_eq!(lines[1].ends_with(",0.5"), true);
+        csv_settings.push(("csv_columns".to_string(), "uuid,sample_rate".to_string()));
+        let edgee_request = Component::page(page_event("user-5"), csv_settings).unwrap();
+        assert_eq!(
+            edgee_request.body.lines().nth(1).unwrap().ends_with(",0.5"),
+            true
+        );
+
+        // sampling is deterministic
+        for _ in 0..3 {
+            assert_eq!(
+                Component::page(page_event("abc"), settings.clone()).is_err(),
+                true
+            );
+            assert_eq!(
+                Component::page(page_event("user-5"), settings.clone()).is_err(),
+                false
+            );
+        }
+
+        // about a quarter of the users are kept with a 0.25 rate
+        let settings = settings_with(&[("sample_rate", "0.25")]);
+        let kept = (0..1000)
+            .filter(|i| Component::page(page_event(&format!("user-{i}")), settings.clone()).is_ok())
+            .count();
+        assert_eq!((200..300).contains(&kept), true, "{kept}");
+
+        // overrides per event type and per track name
+        let settings = settings_with(&[
+            ("sample_rate", "0.1"),
+            ("page_sample_rate", "0.8"),
+            ("track_name_sample_rates", "purchase=1, debug_*=0"),
+        ]);
+        let edgee_request = Component::page(page_event("abc"), settings.clone()).unwrap();
+        assert_eq!(sample_rate(&edgee_request), Some("0.8".to_string()));
+        let edgee_request =
+            Component::track(track_event("purchase", "abc"), settings.clone()).unwrap();
+        assert_eq!(sample_rate(&edgee_request), Some("1".to_string()));
+        assert_eq!(
+            Component::track(track_event("debug_click", "user-7"), settings.clone()).is_err(),
+            true
+        );
+        assert_eq!(
+            Component::track(track_event("signup", "user-5"), settings.clone()).is_err(),
+            true
+        );
+
+        // whole sessions are kept or dropped with a session key (session "random" hashes to ~0.64)
+        for edgee_id in ["abc", "user-5", "user-7"] {
+            let settings = settings_with(&[
+                ("sample_rate", "0.7"),
+                ("sample_key", "context.session.session_id"),
+            ]);
+            assert_eq!(
+                Component::page(page_event(edgee_id), settings).is_ok(),
+                true
+            );
+            let settings = settings_with(&[
+                ("sample_rate", "0.6"),
+                ("sample_key", "context.session.session_id"),
+            ]);
+            assert_eq!(
+                Component::page(page_event(edgee_id), settings).is_err(),
+                true
+            );
+        }
+
+        for (sampling, error) in [
+            (
+                vec![("sample_rate", "1.5")],
+                "Invalid sample rate for sample_rate: 1.5 (expected a number between 0 and 1)",
+            ),
+            (
+                vec![("user_sample_rate", "half")],
+                "Invalid sample rate for user_sample_rate: half",
+            ),
+            (
+                vec![("track_name_sample_rates", "purchase")],
+                "Invalid track name sample rate: purchase (expected pattern=rate)",
+            ),
+            (
+                vec![("track_name_sample_rates", "debug_\u{7}*=0")],
+                "Invalid track name pattern in track_name_sample_rates: debug_\u{7}*",
+            ),
+            (
+                vec![("sample_rate", "0.5"), ("s3_metadata", "sample-rate=1")],
+                "S3 metadata sample-rate is reserved for the sample rate",
+            ),
+            (
+                vec![("sample_rate", "0.5"), ("sample_key", "context.user.nope")],
+                "Unknown sample key: context.user.nope",
+            ),
+            (
+                vec![("sample_key", "context.session.session_id")],
+                "sample_key requires a sample rate",
+            ),
+        ] {
+            let result = Component::page(page_event("abc"), settings_with(&sampling));
+            assert_eq!(result.is_err(), true);
+            assert_eq!(result.err().unwrap().contains(error), true);
+        }
+    }
+
+    #[test]
+    fn breaks_with_invalid_s3_bucket_or_key_prefix() {
+        let event = sample_page_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+        let settings_with = |bucket: &str, key_prefix: &str, force_path_style: &str| {
+            vec![
+                ("aws_access_key".to_string(), "TEST".to_string()),
+                ("aws_secret_key".to_string(), "TEST".to_string()),
+                ("aws_region".to_string(), "us-east-1".to_string()),
+                ("s3_bucket".to_string(), bucket.to_string()),
+                ("s3_key_prefix".to_string(), key_prefix.to_string()),
+                (
+                    "s3_force_path_style".to_string(),
+                    force_path_style.to_string(),
+                ),
+            ]
+        };
+
+        for (bucket, key_prefix, force_path_style, error) in [
+            (
+                "Legacy_Bucket",
+                "events/",
+                "false",
+                "Invalid S3 bucket name for s3_bucket: Legacy_Bucket",
+            ),
+            (
+                "test-bucket-",
+                "events/",
+                "false",
+                "Invalid S3 bucket name for s3_bucket: test-bucket-",
+            ),
+            (
+                "legacy bucket",
+                "events/",
+                "true",
+                "Invalid S3 bucket name for s3_bucket: legacy bucket",
+            ),
+            (
+                "test-bucket",
+                "my events/",
+                "false",
+                "Invalid character ' ' in S3 key prefix for s3_key_prefix: my events/",
+            ),
+        ] {
+            let result = Component::page(
+                event.clone(),
+                settings_with(bucket, key_prefix, force_path_style),
+            );
+            assert_eq!(result.is_err(), true);
+            assert_eq!(result.err().unwrap().contains(error), true);
+        }
+
+        // legacy bucket names are fine in paths
+        let result = Component::page(event, settings_with("Legacy_Bucket", "events/", "true"));
+        assert_eq!(result.is_err(), false);
+        assert_eq!(
+            result
+                .unwrap()
+                .url
+                .starts_with("https://s3.us-east-1.amazonaws.com/Legacy_Bucket/events/"),
+            true
+        );
+    }
+
+    #[test]
+    fn breaks_with_invalid_s3_key_template() {
+        let event = sample_page_event(
+            Some(Consent::Granted),
+            "abc".to_string(),
+            "fr".to_string(),
+            true,
+        );
+
+        for (template, error) in [
+            ("{event_type}/{unknown}", "Unknown placeholder {unknown}"),
+            ("{context.client.nope}", "Unknown placeholder"),
+            ("{event_type}/{random", "Unclosed placeholder"),
+            ("events?/{random}", "Invalid character '?'"),
+            ("{date:%Y %m}/{random}", "produces invalid character ' '"),
+            (
+                "{event_type}/{date:%Y-%m-%d}",
+                "S3 key template must contain {uuid} or {random}",
+            ),
+            (
+                "{context.session.session_id}",
+                "S3 key template must contain {uuid} or {random}",
+            ),
+        ] {
+            let settings = vec![
+                ("aws_access_key".to_string(), "TEST".to_string()),
+                ("aws_secret_key".to_string(), "TEST".to_string()),
+                ("aws_region".to_string(), "eu-west-1".to_string()),
+                ("s3_bucket".to_string(), "test-bucket".to_string()),
+                ("s3_key_template".to_string(), template.to_string()),
+            ];
+            let result = Component::page(event.clone(), settings);
+            assert_eq!(result.is_err(), true);
+            assert_eq!(result.err().unwrap().contains(error), true);
+        }
+    }
+
     #[test]
     fn breaks_without_settings() {
         let event = sample_page_event(
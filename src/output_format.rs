@@ -0,0 +1,150 @@
+use crate::event_fields;
+use crate::exports::edgee::components::data_collection::Event;
+use crate::flat_event::FlatEvent;
+use crate::s3_payload::get_setting;
+use crate::sampling::Sampling;
+use std::collections::HashMap;
+
+const DEFAULT_CSV_COLUMNS: &str = "uuid,timestamp,event_type,consent,context.page.url,context.page.referrer,context.user.edgee_id,context.client.country_code,context.session.session_id";
+
+/// The sample rate isn't an event field, but it can be stored with the event.
+const SAMPLE_RATE_FIELD: &str = "sample_rate";
+
+/// A JSON event with its sample rate next to its own fields.
+#[derive(serde::Serialize)]
+struct SampledEvent<'a> {
+    #[serde(flatten)]
+    event: &'a Event,
+    sample_rate: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputFormat {
+    /// Full JSON dump of the Edgee event, as is.
+    Json,
+    /// One flat JSON object per line, with a stable schema.
+    FlatJson,
+    /// A header row and a data row, with the given event fields as columns.
+    Csv { columns: Vec<String>, delimiter: u8 },
+}
+
+impl OutputFormat {
+    pub fn new(settings_map: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let format = get_setting(settings_map, "output_format");
+        Self::parse(format, settings_map)
+    }
+
+    /// Parses an output format name, with its options (e.g. CSV columns) taken from the settings.
+    pub fn parse(format: &str, settings_map: &HashMap<String, String>) -> anyhow::Result<Self> {
+        match format {
+            "" | "json" => Ok(Self::Json),
+            "flat_json" => Ok(Self::FlatJson),
+            "csv" => {
+                let columns = match get_setting(settings_map, "csv_columns") {
+                    "" => {
+                        let mut columns = parse_csv_columns(DEFAULT_CSV_COLUMNS)?;
+                        if Sampling::new(settings_map)?.is_some() {
+                            columns.push(SAMPLE_RATE_FIELD.to_string());
+                        }
+                        columns
+                    }
+                    columns => parse_csv_columns(columns)?,
+                };
+                let delimiter = parse_csv_delimiter(get_setting(settings_map, "csv_delimiter"))?;
+                Ok(Self::Csv { columns, delimiter })
+            }
+            other => {
+                anyhow::bail!("Invalid output format: {other} (expected json, flat_json or csv)")
+            }
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Json | Self::FlatJson => "json",
+            Self::Csv {
+                delimiter: b'\t', ..
+            } => "tsv",
+            Self::Csv { .. } => "csv",
+        }
+    }
+
+    /// Content-Type of the uploaded objects.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::FlatJson => "application/x-ndjson",
+            Self::Csv {
+                delimiter: b'\t', ..
+            } => "text/tab-separated-values",
+            Self::Csv { .. } => "text/csv",
+        }
+    }
+
+    /// Serializes the event, with the sample rate of sampled events.
+    pub fn serialize(
+        &self,
+        edgee_event: &Event,
+        sample_rate: Option<f64>,
+    ) -> anyhow::Result<String> {
+        match self {
+            Self::Json => match sample_rate {
+                Some(sample_rate) => Ok(serde_json::to_string(&SampledEvent {
+                    event: edgee_event,
+                    sample_rate,
+                })?),
+                None => Ok(serde_json::to_string(edgee_event)?),
+            },
+            Self::FlatJson => {
+                // newline-delimited JSON
+                let mut line = serde_json::to_string(&FlatEvent::new(edgee_event, sample_rate))?;
+                line.push('\n');
+                Ok(line)
+            }
+            Self::Csv { columns, delimiter } => {
+                let mut writer = csv::WriterBuilder::new()
+                    .delimiter(*delimiter)
+                    .from_writer(vec![]);
+                writer.write_record(columns)?;
+                writer.write_record(columns.iter().map(|column| {
+                    if column == SAMPLE_RATE_FIELD {
+                        return sample_rate.map(|rate| rate.to_string()).unwrap_or_default();
+                    }
+                    event_fields::resolve(edgee_event, column).unwrap_or_default()
+                }))?;
+                Ok(String::from_utf8(writer.into_inner()?)?)
+            }
+        }
+    }
+}
+
+fn parse_csv_columns(columns: &str) -> anyhow::Result<Vec<String>> {
+    columns
+        .split(',')
+        .map(str::trim)
+        .map(|column| {
+            if column != SAMPLE_RATE_FIELD && !event_fields::is_known_field(column) {
+                anyhow::bail!("Unknown CSV column: {column}");
+            }
+            Ok(column.to_string())
+        })
+        .collect()
+}
+
+fn parse_csv_delimiter(delimiter: &str) -> anyhow::Result<u8> {
+    match delimiter {
+        "" => Ok(b','),
+        "tab" | "\\t" | "\t" => Ok(b'\t'),
+        _ => {
+            let &[delimiter] = delimiter.as_bytes() else {
+                anyhow::bail!(
+                    "Invalid CSV delimiter: {delimiter} (expected a single character or tab)"
+                );
+            };
+            if delimiter == b'"' || delimiter == b'\n' || delimiter == b'\r' {
+                anyhow::bail!("Invalid CSV delimiter: {:?}", delimiter as char);
+            }
+            Ok(delimiter)
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use crate::event_fields;
+use crate::exports::edgee::components::data_collection::Event;
+use crate::keyed_hash::keyed_hash;
+use crate::s3_payload::{get_setting, split_list};
+use std::collections::HashMap;
+
+/// Event fields replaced by their HMAC-SHA256 before serializing,
+/// so that they stay joinable across objects without being readable.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pseudonymization {
+    pub fields: Vec<String>,
+    pub salt: String,
+}
+
+impl Pseudonymization {
+    pub fn new(settings_map: &HashMap<String, String>) -> anyhow::Result<Option<Self>> {
+        let mut fields: Vec<String> = Vec::new();
+        for field in split_list(get_setting(settings_map, "pseudonymized_fields")) {
+            if !event_fields::is_known_text_field(field) {
+                anyhow::bail!("Invalid pseudonymized field: {field} (expected a text field path)");
+            }
+            if !fields.iter().any(|known| known == field) {
+                fields.push(field.to_string());
+            }
+        }
+
+        let salt = get_setting(settings_map, "pseudonymization_salt");
+        match (fields.is_empty(), salt.is_empty()) {
+            (true, true) => Ok(None),
+            (false, true) => {
+                anyhow::bail!("Pseudonymized fields require a secret pseudonymization_salt")
+            }
+            (true, false) => anyhow::bail!("pseudonymization_salt requires pseudonymized_fields"),
+            (false, false) => Ok(Some(Self {
+                fields,
+                salt: salt.to_string(),
+            })),
+        }
+    }
+
+    /// Replaces the non-empty values of the fields by their keyed hash.
+    pub fn apply(&self, edgee_event: &mut Event) {
+        for field in &self.fields {
+            event_fields::update_text_field(edgee_event, field, |value| {
+                if !value.is_empty() {
+                    *value = keyed_hash(&self.salt, value);
+                }
+            });
+        }
+    }
+}
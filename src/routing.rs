@@ -0,0 +1,209 @@
+use crate::event_fields;
+use crate::exports::edgee::components::data_collection::Event;
+use crate::s3_key::check_key_prefix;
+use crate::s3_payload::{check_bucket_name, get_setting, split_list};
+use crate::s3_storage_class;
+use std::collections::HashMap;
+
+/// Overrides of where an object is stored.
+/// Missing values keep the ones from the settings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Destination {
+    pub bucket: Option<String>,
+    pub region: Option<String>,
+    pub key_prefix: Option<String>,
+    pub storage_class: Option<String>,
+}
+
+impl Destination {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Parses comma-separated `name=value` overrides,
+    /// e.g. `bucket=events-eu, region=eu-west-1, prefix=eu/, storage_class=STANDARD_IA`.
+    fn parse(destination: &str, settings_map: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let mut parsed = Self::default();
+        for item in split_list(destination) {
+            let Some((name, value)) = item.split_once('=') else {
+                anyhow::bail!("Invalid routing destination: {item} (expected name=value)");
+            };
+            let (name, value) = (name.trim(), value.trim().to_string());
+            if value.is_empty() {
+                anyhow::bail!("Empty routing destination value for {name}");
+            }
+            let field = match name {
+                "bucket" => {
+                    check_bucket_name(settings_map, "routing_rules", &value)?;
+                    &mut parsed.bucket
+                }
+                "region" => {
+                    if !value
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+                    {
+                        anyhow::bail!("Invalid routing destination region: {value}");
+                    }
+                    &mut parsed.region
+                }
+                "prefix" => {
+                    check_key_prefix("routing_rules", &value)?;
+                    &mut parsed.key_prefix
+                }
+                "storage_class" => {
+                    s3_storage_class::check_storage_class("routing_rules", &value)?;
+                    &mut parsed.storage_class
+                }
+                other => anyhow::bail!(
+                    "Unknown routing destination: {other} (expected bucket, region, prefix or storage_class)"
+                ),
+            };
+            if field.replace(value).is_some() {
+                anyhow::bail!("Duplicate routing destination: {name}");
+            }
+        }
+        if parsed.is_empty() {
+            anyhow::bail!("Empty routing destination: {destination}");
+        }
+        Ok(parsed)
+    }
+}
+
+/// A condition on an event field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Equals(String, String),
+    NotEquals(String, String),
+    In(String, Vec<String>),
+    NotIn(String, Vec<String>),
+    StartsWith(String, String),
+    EndsWith(String, String),
+    Contains(String, String),
+}
+
+impl Condition {
+    /// Parses `<field> <operator> <value>`, e.g. `context.client.country_code in [DE, FR]`.
+    /// Values may be double-quoted, to keep their spaces.
+    fn parse(condition: &str) -> anyhow::Result<Self> {
+        let (field, rest) = condition
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| anyhow::anyhow!("Invalid routing condition: {condition}"))?;
+        if !event_fields::is_known_field(field) {
+            anyhow::bail!("Unknown routing condition field: {field}");
+        }
+        let field = field.to_string();
+        let rest = rest.trim_start();
+
+        let (operator, value) = if let Some(value) = rest.strip_prefix("not in ") {
+            ("not in", value)
+        } else {
+            rest.split_once(' ')
+                .ok_or_else(|| anyhow::anyhow!("Invalid routing condition: {condition}"))?
+        };
+        let value = value.trim();
+
+        let condition = match operator {
+            "==" => Self::Equals(field, parse_value(value)?),
+            "!=" => Self::NotEquals(field, parse_value(value)?),
+            "in" => Self::In(field, parse_list(value)?),
+            "not in" => Self::NotIn(field, parse_list(value)?),
+            "startswith" => Self::StartsWith(field, parse_value(value)?),
+            "endswith" => Self::EndsWith(field, parse_value(value)?),
+            "contains" => Self::Contains(field, parse_value(value)?),
+            other => anyhow::bail!(
+                "Unknown routing condition operator: {other} (expected ==, !=, in, not in, startswith, endswith or contains)"
+            ),
+        };
+        Ok(condition)
+    }
+
+    /// Fields missing from the event (e.g. `data.name` on user events) never match.
+    pub fn matches(&self, edgee_event: &Event) -> bool {
+        let field = match self {
+            Self::Equals(field, _)
+            | Self::NotEquals(field, _)
+            | Self::In(field, _)
+            | Self::NotIn(field, _)
+            | Self::StartsWith(field, _)
+            | Self::EndsWith(field, _)
+            | Self::Contains(field, _) => field,
+        };
+        let Some(value) = event_fields::resolve(edgee_event, field) else {
+            return false;
+        };
+        match self {
+            Self::Equals(_, expected) => value == *expected,
+            Self::NotEquals(_, expected) => value != *expected,
+            Self::In(_, values) => values.contains(&value),
+            Self::NotIn(_, values) => !values.contains(&value),
+            Self::StartsWith(_, prefix) => value.starts_with(prefix.as_str()),
+            Self::EndsWith(_, suffix) => value.ends_with(suffix.as_str()),
+            Self::Contains(_, part) => value.contains(part.as_str()),
+        }
+    }
+}
+
+fn parse_value(value: &str) -> anyhow::Result<String> {
+    let unquoted = match value.strip_prefix('"') {
+        Some(quoted) => quoted
+            .strip_suffix('"')
+            .ok_or_else(|| anyhow::anyhow!("Unclosed quote in routing condition value: {value}"))?,
+        None => value,
+    };
+    if unquoted.is_empty() && !value.starts_with('"') {
+        anyhow::bail!("Missing routing condition value");
+    }
+    Ok(unquoted.to_string())
+}
+
+fn parse_list(list: &str) -> anyhow::Result<Vec<String>> {
+    let items = list
+        .strip_prefix('[')
+        .and_then(|list| list.strip_suffix(']'))
+        .ok_or_else(|| {
+            anyhow::anyhow!("Invalid routing condition list: {list} (expected [a, b])")
+        })?;
+    split_list(items).map(parse_value).collect()
+}
+
+/// Ordered routing rules: the destination of the first matching condition is used,
+/// and events matching no rule keep the default destination from the settings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RoutingRules {
+    pub rules: Vec<(Condition, Destination)>,
+}
+
+impl RoutingRules {
+    /// Parses `routing_rules`: `;`-separated `<condition> => <destination>` rules.
+    pub fn new(settings_map: &HashMap<String, String>) -> anyhow::Result<Option<Self>> {
+        let rules = get_setting(settings_map, "routing_rules");
+
+        let mut routing_rules = Self::default();
+        for rule in rules
+            .split(';')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+        {
+            let Some((condition, destination)) = rule.split_once("=>") else {
+                anyhow::bail!("Invalid routing rule: {rule} (expected condition => destination)");
+            };
+            routing_rules.rules.push((
+                Condition::parse(condition)?,
+                Destination::parse(destination, settings_map)?,
+            ));
+        }
+
+        if routing_rules.rules.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(routing_rules))
+    }
+
+    pub fn destination(&self, edgee_event: &Event) -> Option<&Destination> {
+        self.rules
+            .iter()
+            .find(|(condition, _)| condition.matches(edgee_event))
+            .map(|(_, destination)| destination)
+    }
+}
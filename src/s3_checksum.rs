@@ -0,0 +1,70 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use crc::{Crc, CRC_32_ISCSI, CRC_32_ISO_HDLC, CRC_64_NVME};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+const CRC32C: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+const CRC64NVME: Crc<u64> = Crc::<u64>::new(&CRC_64_NVME);
+
+/// Flexible checksum (x-amz-checksum-*), checked by S3 against the received body.
+/// https://docs.aws.amazon.com/AmazonS3/latest/userguide/checking-object-integrity.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChecksumAlgorithm {
+    Crc32,
+    Crc32c,
+    Sha1,
+    Sha256,
+    Crc64Nvme,
+}
+
+impl ChecksumAlgorithm {
+    pub fn parse(value: &str) -> anyhow::Result<Option<Self>> {
+        match value.to_ascii_uppercase().as_str() {
+            "" => Ok(None),
+            "CRC32" => Ok(Some(Self::Crc32)),
+            "CRC32C" => Ok(Some(Self::Crc32c)),
+            "SHA1" => Ok(Some(Self::Sha1)),
+            "SHA256" => Ok(Some(Self::Sha256)),
+            "CRC64NVME" => Ok(Some(Self::Crc64Nvme)),
+            _ => anyhow::bail!(
+                "Invalid S3 checksum algorithm: {value} (expected CRC32, CRC32C, SHA1, SHA256 or CRC64NVME)"
+            ),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Crc32 => "CRC32",
+            Self::Crc32c => "CRC32C",
+            Self::Sha1 => "SHA1",
+            Self::Sha256 => "SHA256",
+            Self::Crc64Nvme => "CRC64NVME",
+        }
+    }
+
+    /// Base64 encoding of the big-endian checksum, as expected by S3.
+    pub fn checksum(&self, body: &[u8]) -> String {
+        match self {
+            Self::Crc32 => BASE64.encode(CRC32.checksum(body).to_be_bytes()),
+            Self::Crc32c => BASE64.encode(CRC32C.checksum(body).to_be_bytes()),
+            Self::Sha1 => BASE64.encode(Sha1::digest(body)),
+            Self::Sha256 => BASE64.encode(Sha256::digest(body)),
+            Self::Crc64Nvme => BASE64.encode(CRC64NVME.checksum(body).to_be_bytes()),
+        }
+    }
+
+    pub fn headers(&self, body: &[u8]) -> Vec<(String, String)> {
+        vec![
+            (
+                format!("x-amz-checksum-{}", self.name().to_ascii_lowercase()),
+                self.checksum(body),
+            ),
+            (
+                "x-amz-sdk-checksum-algorithm".to_string(),
+                self.name().to_string(),
+            ),
+        ]
+    }
+}
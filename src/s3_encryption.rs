@@ -0,0 +1,114 @@
+use crate::s3_payload::{get_setting, parse_bool_setting};
+use anyhow::Context;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use md5::{Digest, Md5};
+use std::collections::HashMap;
+
+/// Server-side encryption of the uploaded objects.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerSideEncryption {
+    /// SSE-S3, with keys managed by S3.
+    S3,
+    /// SSE-KMS (aws:kms) or DSSE-KMS (aws:kms:dsse).
+    Kms {
+        algorithm: String,
+        key_id: String, // could be empty (AWS managed key)
+        bucket_key_enabled: bool,
+    },
+    /// SSE-C, with a customer-provided AES-256 key.
+    Customer { key: String, key_md5: String },
+}
+
+impl ServerSideEncryption {
+    pub fn new(settings_map: &HashMap<String, String>) -> anyhow::Result<Option<Self>> {
+        let algorithm = get_setting(settings_map, "s3_server_side_encryption");
+        let kms_key_id = get_setting(settings_map, "s3_sse_kms_key_id");
+        let bucket_key_enabled = parse_bool_setting(settings_map, "s3_sse_bucket_key_enabled")?;
+        let customer_key = get_setting(settings_map, "s3_sse_customer_key");
+
+        // checked first, so that they are rejected with SSE-C too
+        if !matches!(algorithm, "aws:kms" | "aws:kms:dsse")
+            && (!kms_key_id.is_empty() || bucket_key_enabled)
+        {
+            anyhow::bail!(
+                "KMS settings require s3_server_side_encryption = aws:kms or aws:kms:dsse"
+            );
+        }
+
+        if !customer_key.is_empty() {
+            if !algorithm.is_empty() {
+                anyhow::bail!("SSE-C can't be combined with s3_server_side_encryption");
+            }
+            let key = BASE64
+                .decode(customer_key)
+                .context("Invalid SSE-C customer key (expected base64)")?;
+            if key.len() != 32 {
+                anyhow::bail!("Invalid SSE-C customer key (expected a 256-bit key)");
+            }
+            return Ok(Some(Self::Customer {
+                key: customer_key.to_string(),
+                key_md5: BASE64.encode(Md5::digest(&key)),
+            }));
+        }
+
+        Ok(match algorithm {
+            "" => None,
+            "AES256" => Some(Self::S3),
+            "aws:kms" | "aws:kms:dsse" => Some(Self::Kms {
+                algorithm: algorithm.to_string(),
+                key_id: kms_key_id.to_string(),
+                bucket_key_enabled,
+            }),
+            other => anyhow::bail!(
+                "Invalid server-side encryption: {other} (expected AES256, aws:kms or aws:kms:dsse)"
+            ),
+        })
+    }
+
+    pub fn headers(&self) -> Vec<(String, String)> {
+        match self {
+            Self::S3 => vec![(
+                "x-amz-server-side-encryption".to_string(),
+                "AES256".to_string(),
+            )],
+            Self::Kms {
+                algorithm,
+                key_id,
+                bucket_key_enabled,
+            } => {
+                let mut headers = vec![(
+                    "x-amz-server-side-encryption".to_string(),
+                    algorithm.clone(),
+                )];
+                if !key_id.is_empty() {
+                    headers.push((
+                        "x-amz-server-side-encryption-aws-kms-key-id".to_string(),
+                        key_id.clone(),
+                    ));
+                }
+                if *bucket_key_enabled {
+                    headers.push((
+                        "x-amz-server-side-encryption-bucket-key-enabled".to_string(),
+                        "true".to_string(),
+                    ));
+                }
+                headers
+            }
+            Self::Customer { key, key_md5 } => vec![
+                (
+                    "x-amz-server-side-encryption-customer-algorithm".to_string(),
+                    "AES256".to_string(),
+                ),
+                (
+                    "x-amz-server-side-encryption-customer-key".to_string(),
+                    key.clone(),
+                ),
+                (
+                    "x-amz-server-side-encryption-customer-key-md5".to_string(),
+                    key_md5.clone(),
+                ),
+            ],
+        }
+    }
+}
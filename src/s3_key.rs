@@ -0,0 +1,162 @@
+use crate::event_fields;
+use crate::exports::edgee::components::data_collection::Event;
+use chrono::format::{Item, StrftimeItems};
+use chrono::DateTime;
+use uuid::Uuid;
+
+/// A parsed `s3_key_template`, such as
+/// `{event_type}/{date:%Y/%m/%d}/{context.client.country_code}-{random}`.
+///
+/// Supported placeholders:
+/// - `{event_type}`: page, track or user
+/// - `{uuid}`: the Edgee event UUID
+/// - `{date:<strftime format>}`: the event timestamp
+/// - `{random}`: a random UUID, to avoid collisions
+/// - any event field path, e.g. `{data.name}` or `{context.session.session_id}`
+///
+/// Templates must contain `{uuid}` or `{random}`, so that each event gets its own object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyTemplate {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    EventType,
+    Uuid,
+    Date(String),
+    Random,
+    Field(String),
+}
+
+impl KeyTemplate {
+    pub fn parse(template: &str) -> anyhow::Result<Self> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+
+        while !rest.is_empty() {
+            let Some(start) = rest.find('{') else {
+                segments.push(Segment::literal(rest)?);
+                break;
+            };
+            if start > 0 {
+                segments.push(Segment::literal(&rest[..start])?);
+            }
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| anyhow::anyhow!("Unclosed placeholder in S3 key template"))?;
+            segments.push(Segment::placeholder(&rest[start + 1..end])?);
+            rest = &rest[end + 1..];
+        }
+
+        if segments.is_empty() {
+            anyhow::bail!("Empty S3 key template");
+        }
+        // without a unique part, events would overwrite each other's objects
+        if !segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Uuid | Segment::Random))
+        {
+            anyhow::bail!("S3 key template must contain {{uuid}} or {{random}}");
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Whether rendering the same event twice gives the same key.
+    pub fn is_idempotent(&self) -> bool {
+        self.segments.contains(&Segment::Uuid) && !self.segments.contains(&Segment::Random)
+    }
+
+    /// Renders the template for the given event, without extension.
+    pub fn render(&self, edgee_event: &Event) -> String {
+        let datetime = event_fields::event_datetime(edgee_event);
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => literal.clone(),
+                Segment::EventType => {
+                    event_fields::event_type_name(&edgee_event.event_type).to_string()
+                }
+                Segment::Uuid => sanitize_value(&edgee_event.uuid),
+                Segment::Date(format) => datetime.format(format).to_string(),
+                Segment::Random => Uuid::new_v4().to_string(),
+                Segment::Field(path) => {
+                    sanitize_value(&event_fields::resolve(edgee_event, path).unwrap_or_default())
+                }
+            })
+            .collect()
+    }
+}
+
+impl Segment {
+    fn literal(literal: &str) -> anyhow::Result<Self> {
+        if let Some(c) = literal.chars().find(|c| !is_safe_key_char(*c)) {
+            anyhow::bail!("Invalid character {c:?} in S3 key template");
+        }
+        Ok(Self::Literal(literal.to_string()))
+    }
+
+    fn placeholder(placeholder: &str) -> anyhow::Result<Self> {
+        match placeholder {
+            "event_type" => Ok(Self::EventType),
+            "uuid" => Ok(Self::Uuid),
+            "random" => Ok(Self::Random),
+            _ => {
+                if let Some(format) = placeholder.strip_prefix("date:") {
+                    validate_date_format(format)?;
+                    return Ok(Self::Date(format.to_string()));
+                }
+                if event_fields::is_known_field(placeholder) {
+                    return Ok(Self::Field(placeholder.to_string()));
+                }
+                anyhow::bail!("Unknown placeholder {{{placeholder}}} in S3 key template")
+            }
+        }
+    }
+}
+
+fn validate_date_format(format: &str) -> anyhow::Result<()> {
+    if format.is_empty() || StrftimeItems::new(format).any(|item| item == Item::Error) {
+        anyhow::bail!("Invalid date format {format:?} in S3 key template");
+    }
+    // make sure the formatted date only contains characters that are safe in keys
+    let sample = DateTime::from_timestamp(0, 0).unwrap_or_default();
+    let formatted = sample.format(format).to_string();
+    if let Some(c) = formatted.chars().find(|c| !is_safe_key_char(*c)) {
+        anyhow::bail!("Date format {format:?} produces invalid character {c:?} in S3 key template");
+    }
+    Ok(())
+}
+
+/// Checks that a key prefix only contains characters that are safe in S3 keys.
+pub fn check_key_prefix(key: &str, prefix: &str) -> anyhow::Result<()> {
+    if let Some(c) = prefix.chars().find(|c| !is_safe_key_char(*c)) {
+        anyhow::bail!("Invalid character {c:?} in S3 key prefix for {key}: {prefix}");
+    }
+    Ok(())
+}
+
+/// Characters that are always safe in S3 keys, plus '=' for Hive-style partitions.
+fn is_safe_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!-_.*'()/=".contains(c)
+}
+
+/// Event values can't add sub-folders or unsafe characters to the key.
+fn sanitize_value(value: &str) -> String {
+    if value.is_empty() {
+        return "unknown".to_string();
+    }
+    value
+        .chars()
+        .map(|c| {
+            if is_safe_key_char(c) && c != '/' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,155 @@
+use crate::event_fields;
+use crate::exports::edgee::components::data_collection::Event;
+use crate::s3_payload::{get_setting, split_list};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use std::collections::HashMap;
+
+// https://docs.aws.amazon.com/AmazonS3/latest/userguide/UsingMetadata.html
+const MAX_METADATA_SIZE: usize = 2048; // names and values, in bytes
+const MAX_EVENT_VALUE_LENGTH: usize = 256;
+
+/// Metadata recording the sample rate of sampled events.
+const SAMPLE_RATE_NAME: &str = "sample-rate";
+const MAX_SAMPLE_RATE_LENGTH: usize = 24; // e.g. 0.30000000000000004
+
+const COMPONENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Control and non-ASCII characters, and % to keep the encoding unambiguous.
+const VALUE_ENCODE_SET: &AsciiSet = &CONTROLS.add(b'%');
+
+/// User-defined object metadata (x-amz-meta-* headers), static or taken from the event,
+/// so that S3 event notifications can route objects without reading them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectMetadata {
+    pub static_metadata: Vec<(String, String)>,
+    pub event_metadata: Vec<(String, String)>, // name and event field path (or component_version)
+}
+
+impl ObjectMetadata {
+    pub fn new(settings_map: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let mut metadata = Self::default();
+
+        for (name, value) in parse_pairs("s3_metadata", get_setting(settings_map, "s3_metadata"))? {
+            if !value.chars().all(|c| c.is_ascii() && !c.is_ascii_control()) {
+                anyhow::bail!("S3 metadata {name} must be printable US-ASCII");
+            }
+            metadata.static_metadata.push((name, value));
+        }
+
+        for (name, source) in parse_pairs(
+            "s3_event_metadata",
+            get_setting(settings_map, "s3_event_metadata"),
+        )? {
+            if source != "component_version" && !event_fields::is_known_field(&source) {
+                anyhow::bail!("Unknown event field for S3 metadata {name}: {source}");
+            }
+            metadata.event_metadata.push((name, source));
+        }
+
+        let mut names: Vec<&str> = metadata
+            .static_metadata
+            .iter()
+            .chain(&metadata.event_metadata)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort_unstable();
+        if let Some(name) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+            anyhow::bail!("Duplicate S3 metadata: {}", name[0]);
+        }
+
+        metadata.check_size(0)?;
+        Ok(metadata)
+    }
+
+    /// Makes sure that the sample rate of sampled events can be added to the metadata.
+    pub fn reserve_sample_rate(&self) -> anyhow::Result<()> {
+        if self
+            .static_metadata
+            .iter()
+            .chain(&self.event_metadata)
+            .any(|(name, _)| name == SAMPLE_RATE_NAME)
+        {
+            anyhow::bail!("S3 metadata {SAMPLE_RATE_NAME} is reserved for the sample rate");
+        }
+        self.check_size(SAMPLE_RATE_NAME.len() + MAX_SAMPLE_RATE_LENGTH)
+    }
+
+    pub fn set_sample_rate(&mut self, rate: f64) {
+        self.static_metadata
+            .push((SAMPLE_RATE_NAME.to_string(), rate.to_string()));
+    }
+
+    /// Checks the size limit upfront, with event values at their maximum length and reserved bytes on top.
+    fn check_size(&self, reserved: usize) -> anyhow::Result<()> {
+        let size: usize = self
+            .static_metadata
+            .iter()
+            .map(|(name, value)| name.len() + value.len())
+            .chain(
+                self.event_metadata
+                    .iter()
+                    .map(|(name, _)| name.len() + MAX_EVENT_VALUE_LENGTH),
+            )
+            .sum::<usize>()
+            + reserved;
+        if size > MAX_METADATA_SIZE {
+            anyhow::bail!(
+                "S3 metadata is too large: up to {size} bytes (at most {MAX_METADATA_SIZE}, event values count for {MAX_EVENT_VALUE_LENGTH})"
+            );
+        }
+        Ok(())
+    }
+
+    pub fn headers(&self, edgee_event: &Event) -> Vec<(String, String)> {
+        let event_metadata = self.event_metadata.iter().filter_map(|(name, source)| {
+            let value = match source.as_str() {
+                "component_version" => COMPONENT_VERSION.to_string(),
+                path => event_fields::resolve(edgee_event, path)?,
+            };
+            Some((name.clone(), encode_event_value(&value)))
+        });
+
+        self.static_metadata
+            .iter()
+            .cloned()
+            .chain(event_metadata)
+            .map(|(name, value)| (format!("x-amz-meta-{name}"), value))
+            .collect()
+    }
+}
+
+/// Parses comma-separated `name=value` pairs, with names valid in a header name.
+fn parse_pairs(key: &str, pairs: &str) -> anyhow::Result<Vec<(String, String)>> {
+    split_list(pairs)
+        .map(|pair| {
+            let Some((name, value)) = pair.split_once('=') else {
+                anyhow::bail!("Invalid {key} entry: {pair} (expected name=value)");
+            };
+            let name = name.trim().to_ascii_lowercase();
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                anyhow::bail!(
+                    "Invalid S3 metadata name: {name} (expected letters, digits, - or _)"
+                );
+            }
+            Ok((name, value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Header values must be US-ASCII: other characters are percent-encoded,
+/// and long values are truncated to keep the metadata under S3's limit.
+fn encode_event_value(value: &str) -> String {
+    let encoded = utf8_percent_encode(value.trim(), VALUE_ENCODE_SET).to_string();
+    let mut end = encoded.len().min(MAX_EVENT_VALUE_LENGTH);
+    // don't cut a percent-encoded sequence in half
+    if let Some(percent) = encoded[end.saturating_sub(2)..end].find('%') {
+        if end < encoded.len() {
+            end = end.saturating_sub(2) + percent;
+        }
+    }
+    encoded[..end].to_string()
+}
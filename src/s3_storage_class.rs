@@ -0,0 +1,77 @@
+use crate::exports::edgee::components::data_collection::EventType;
+use std::collections::HashMap;
+
+const STORAGE_CLASSES: &[&str] = &[
+    "STANDARD",
+    "REDUCED_REDUNDANCY",
+    "STANDARD_IA",
+    "ONEZONE_IA",
+    "INTELLIGENT_TIERING",
+    "GLACIER",
+    "GLACIER_IR",
+    "DEEP_ARCHIVE",
+    "OUTPOSTS",
+    "SNOW",
+    "EXPRESS_ONEZONE",
+];
+
+/// Storage class of the uploaded objects (x-amz-storage-class),
+/// with optional overrides per event type.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StorageClasses {
+    pub default: Option<String>,
+    pub page: Option<String>,
+    pub track: Option<String>,
+    pub user: Option<String>,
+}
+
+impl StorageClasses {
+    pub fn new(settings_map: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let parse = |key: &str| -> anyhow::Result<Option<String>> {
+            match settings_map.get(key).map(String::as_str) {
+                None | Some("") => Ok(None),
+                Some(storage_class) => {
+                    check_storage_class(key, storage_class)?;
+                    Ok(Some(storage_class.to_string()))
+                }
+            }
+        };
+
+        Ok(Self {
+            default: parse("s3_storage_class")?,
+            page: parse("page_s3_storage_class")?,
+            track: parse("track_s3_storage_class")?,
+            user: parse("user_s3_storage_class")?,
+        })
+    }
+
+    /// Uses the same storage class for all event types.
+    pub fn set_all(&mut self, storage_class: &str) {
+        *self = Self {
+            default: Some(storage_class.to_string()),
+            ..Self::default()
+        };
+    }
+
+    pub fn for_event_type(&self, event_type: &EventType) -> Option<&str> {
+        let storage_class = match event_type {
+            EventType::Page => &self.page,
+            EventType::Track => &self.track,
+            EventType::User => &self.user,
+        };
+        storage_class
+            .as_ref()
+            .or(self.default.as_ref())
+            .map(String::as_str)
+    }
+}
+
+pub fn check_storage_class(key: &str, storage_class: &str) -> anyhow::Result<()> {
+    if !STORAGE_CLASSES.contains(&storage_class) {
+        anyhow::bail!(
+            "Invalid S3 storage class for {key}: {storage_class} (expected one of {})",
+            STORAGE_CLASSES.join(", ")
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use crate::event_fields::{consent_name, event_type_name};
+use crate::exports::edgee::components::data_collection::{Data, Event};
+use crate::s3_payload::{get_setting, split_list};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::collections::HashMap;
+
+// https://docs.aws.amazon.com/AmazonS3/latest/userguide/object-tagging.html
+const MAX_TAGS: usize = 10;
+const MAX_KEY_LENGTH: usize = 128;
+const MAX_VALUE_LENGTH: usize = 256;
+
+/// Tags that can be taken from the event.
+const EVENT_TAGS: &[&str] = &["event_type", "consent", "country_code", "track_name"];
+
+/// Everything but unreserved characters is percent-encoded.
+const TAG_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Object tags (x-amz-tagging), static or taken from the event,
+/// so that lifecycle rules and IAM conditions can act on individual objects.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tagging {
+    pub static_tags: Vec<(String, String)>,
+    pub event_tags: Vec<String>,
+}
+
+impl Tagging {
+    pub fn new(settings_map: &HashMap<String, String>) -> anyhow::Result<Option<Self>> {
+        let mut tagging = Self::default();
+
+        for tag in split_list(get_setting(settings_map, "s3_tags")) {
+            let Some((key, value)) = tag.split_once('=') else {
+                anyhow::bail!("Invalid S3 tag: {tag} (expected key=value)");
+            };
+            let (key, value) = (key.trim(), value.trim());
+            check_tag_key(key)?;
+            if value.chars().count() > MAX_VALUE_LENGTH {
+                anyhow::bail!(
+                    "S3 tag value for {key} is longer than {MAX_VALUE_LENGTH} characters"
+                );
+            }
+            if !value.chars().all(is_allowed_char) {
+                anyhow::bail!("S3 tag value for {key} contains invalid characters: {value}");
+            }
+            tagging
+                .static_tags
+                .push((key.to_string(), value.to_string()));
+        }
+
+        for tag in split_list(get_setting(settings_map, "s3_event_tags")) {
+            if !EVENT_TAGS.contains(&tag) {
+                anyhow::bail!(
+                    "Invalid S3 event tag: {tag} (expected one of {})",
+                    EVENT_TAGS.join(", ")
+                );
+            }
+            tagging.event_tags.push(tag.to_string());
+        }
+
+        let mut keys: Vec<&str> = tagging
+            .static_tags
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .chain(tagging.event_tags.iter().map(String::as_str))
+            .collect();
+        if keys.len() > MAX_TAGS {
+            anyhow::bail!("Too many S3 tags: {} (at most {MAX_TAGS})", keys.len());
+        }
+        keys.sort_unstable();
+        if let Some(key) = keys.windows(2).find(|pair| pair[0] == pair[1]) {
+            anyhow::bail!("Duplicate S3 tag: {}", key[0]);
+        }
+
+        if keys.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(tagging))
+    }
+
+    /// Value of the x-amz-tagging header, URL-encoded as a query string
+    /// (e.g. `team=analytics&event_type=page&consent=granted`).
+    pub fn header_value(&self, edgee_event: &Event) -> String {
+        let event_tags = self.event_tags.iter().filter_map(|tag| {
+            let value = match tag.as_str() {
+                "event_type" => event_type_name(&edgee_event.event_type).to_string(),
+                "consent" => consent_name(edgee_event.consent.as_ref()).to_string(),
+                "country_code" => edgee_event.context.client.country_code.clone(),
+                "track_name" => match &edgee_event.data {
+                    Data::Track(track) => track.name.clone(),
+                    _ => return None, // only tracked events have a name
+                },
+                _ => return None,
+            };
+            Some((tag.clone(), sanitize_tag_value(&value)))
+        });
+
+        self.static_tags
+            .iter()
+            .cloned()
+            .chain(event_tags)
+            .map(|(key, value)| {
+                format!(
+                    "{}={}",
+                    utf8_percent_encode(&key, TAG_ENCODE_SET),
+                    utf8_percent_encode(&value, TAG_ENCODE_SET),
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+}
+
+fn check_tag_key(key: &str) -> anyhow::Result<()> {
+    if key.is_empty() {
+        anyhow::bail!("S3 tag keys can't be empty");
+    }
+    if key.chars().count() > MAX_KEY_LENGTH {
+        anyhow::bail!("S3 tag key {key} is longer than {MAX_KEY_LENGTH} characters");
+    }
+    if key.starts_with("aws:") {
+        anyhow::bail!("S3 tag key {key} uses the reserved aws: prefix");
+    }
+    if !key.chars().all(is_allowed_char) {
+        anyhow::bail!("S3 tag key {key} contains invalid characters");
+    }
+    Ok(())
+}
+
+/// Letters, numbers, spaces and `+ - = . _ : / @` are allowed in tags.
+fn is_allowed_char(c: char) -> bool {
+    c.is_alphanumeric() || c == ' ' || "+-=._:/@".contains(c)
+}
+
+/// Event values can't be rejected at this point, so invalid characters
+/// are replaced and long values are truncated instead.
+fn sanitize_tag_value(value: &str) -> String {
+    value
+        .chars()
+        .take(MAX_VALUE_LENGTH)
+        .map(|c| if is_allowed_char(c) { c } else { '_' })
+        .collect()
+}
@@ -0,0 +1,133 @@
+use crate::event_fields;
+use crate::exports::edgee::components::data_collection::{Data, Event, EventType};
+use crate::s3_payload::{get_setting, split_list};
+use crate::track_names::{check_pattern, glob_match};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+const DEFAULT_SAMPLE_KEY: &str = "context.user.edgee_id";
+
+/// Deterministic sampling: events are kept or dropped depending on a hash of a key field,
+/// so that all the events sharing that key (e.g. a user or a session) get the same fate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sampling {
+    pub default_rate: f64,
+    pub page_rate: Option<f64>,
+    pub track_rate: Option<f64>,
+    pub user_rate: Option<f64>,
+    /// Rates per track name pattern, the first matching one wins.
+    pub track_name_rates: Vec<(String, f64)>,
+    /// Field path hashed to decide whether an event is kept.
+    pub key: String,
+}
+
+impl Sampling {
+    pub fn new(settings_map: &HashMap<String, String>) -> anyhow::Result<Option<Self>> {
+        let mut track_name_rates = Vec::new();
+        for rule in split_list(get_setting(settings_map, "track_name_sample_rates")) {
+            let Some((pattern, rate)) = rule.split_once('=') else {
+                anyhow::bail!("Invalid track name sample rate: {rule} (expected pattern=rate)");
+            };
+            let pattern = pattern.trim();
+            check_pattern(pattern, "track_name_sample_rates")?;
+            track_name_rates.push((
+                pattern.to_string(),
+                parse_rate("track_name_sample_rates", rate.trim())?,
+            ));
+        }
+
+        let default_rate = match get_setting(settings_map, "sample_rate") {
+            "" => None,
+            rate => Some(parse_rate("sample_rate", rate)?),
+        };
+        let parse_optional_rate = |key: &str| -> anyhow::Result<Option<f64>> {
+            match get_setting(settings_map, key) {
+                "" => Ok(None),
+                rate => Ok(Some(parse_rate(key, rate)?)),
+            }
+        };
+        let page_rate = parse_optional_rate("page_sample_rate")?;
+        let track_rate = parse_optional_rate("track_sample_rate")?;
+        let user_rate = parse_optional_rate("user_sample_rate")?;
+
+        let key = get_setting(settings_map, "sample_key");
+        if default_rate.is_none()
+            && page_rate.is_none()
+            && track_rate.is_none()
+            && user_rate.is_none()
+            && track_name_rates.is_empty()
+        {
+            if !key.is_empty() {
+                anyhow::bail!("sample_key requires a sample rate");
+            }
+            return Ok(None);
+        }
+        let key = match key {
+            "" => DEFAULT_SAMPLE_KEY,
+            key if event_fields::is_known_field(key) => key,
+            key => anyhow::bail!("Unknown sample key: {key}"),
+        };
+
+        Ok(Some(Self {
+            default_rate: default_rate.unwrap_or(1.0),
+            page_rate,
+            track_rate,
+            user_rate,
+            track_name_rates,
+            key: key.to_string(),
+        }))
+    }
+
+    /// Sample rate of the event: its track name rate, its event type rate, or the default one.
+    pub fn rate(&self, edgee_event: &Event) -> f64 {
+        if let Data::Track(track) = &edgee_event.data {
+            if let Some((_, rate)) = self
+                .track_name_rates
+                .iter()
+                .find(|(pattern, _)| glob_match(pattern, &track.name))
+            {
+                return *rate;
+            }
+        }
+        let event_type_rate = match edgee_event.event_type {
+            EventType::Page => self.page_rate,
+            EventType::Track => self.track_rate,
+            EventType::User => self.user_rate,
+        };
+        event_type_rate.unwrap_or(self.default_rate)
+    }
+
+    /// Returns the sample rate of kept events, or an error for sampled out events.
+    pub fn apply(&self, edgee_event: &Event) -> anyhow::Result<f64> {
+        let rate = self.rate(edgee_event);
+
+        // events without a key are sampled on their UUID
+        let key = event_fields::resolve(edgee_event, &self.key)
+            .filter(|key| !key.is_empty())
+            .unwrap_or_else(|| edgee_event.uuid.clone());
+        if sample_position(&key) >= rate {
+            anyhow::bail!("Event sampled out (sample rate {rate})");
+        }
+        Ok(rate)
+    }
+}
+
+fn parse_rate(key: &str, rate: &str) -> anyhow::Result<f64> {
+    match rate.parse::<f64>() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+        _ => anyhow::bail!(
+            "Invalid sample rate for {key}: {rate} (expected a number between 0 and 1)"
+        ),
+    }
+}
+
+/// Position of a key in [0, 1), from the first 8 bytes of its SHA-256.
+/// Stable across versions and platforms, unlike the std hashers.
+fn sample_position(key: &str) -> f64 {
+    let digest = Sha256::digest(key.as_bytes());
+    let bytes: [u8; 8] = digest[..8]
+        .try_into()
+        .expect("SHA-256 digests are 32 bytes");
+    // the top 53 bits fit exactly in an f64
+    (u64::from_be_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,157 @@
+use crate::exports::edgee::components::data_collection::{Data, Event};
+use crate::routing::Destination;
+use crate::s3_key::check_key_prefix;
+use crate::s3_payload::{get_setting, split_list};
+use std::collections::HashMap;
+
+/// Filtering and key prefix routing of track events, on their name.
+/// Patterns are globs: `*` matches any sequence of characters and `?` a single one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackNameRules {
+    /// Track events are only sent if their name matches one of these patterns (all of them when empty).
+    pub allow: Vec<String>,
+    /// Track events whose name matches one of these patterns aren't sent.
+    pub deny: Vec<String>,
+    /// Key prefixes per name pattern, the first matching one wins.
+    pub key_prefixes: Vec<(String, String)>,
+}
+
+impl TrackNameRules {
+    pub fn new(settings_map: &HashMap<String, String>) -> anyhow::Result<Option<Self>> {
+        let mut rules = Self {
+            allow: parse_patterns(
+                get_setting(settings_map, "track_name_allowlist"),
+                "track_name_allowlist",
+            )?,
+            deny: parse_patterns(
+                get_setting(settings_map, "track_name_denylist"),
+                "track_name_denylist",
+            )?,
+            key_prefixes: Vec::new(),
+        };
+
+        for rule in split_list(get_setting(settings_map, "track_name_s3_key_prefixes")) {
+            let Some((pattern, key_prefix)) = rule.split_once('=') else {
+                anyhow::bail!("Invalid track name key prefix: {rule} (expected pattern=prefix)");
+            };
+            let (pattern, key_prefix) = (pattern.trim(), key_prefix.trim());
+            check_pattern(pattern, "track_name_s3_key_prefixes")?;
+            check_key_prefix("track_name_s3_key_prefixes", key_prefix)?;
+            rules
+                .key_prefixes
+                .push((pattern.to_string(), key_prefix.to_string()));
+        }
+
+        if rules == Self::default() {
+            return Ok(None);
+        }
+        Ok(Some(rules))
+    }
+
+    /// Returns an error for filtered out track events,
+    /// and the destination of the track events with a key prefix rule.
+    /// Other event types are left as is.
+    pub fn apply(&self, edgee_event: &Event) -> anyhow::Result<Option<Destination>> {
+        let Data::Track(track) = &edgee_event.data else {
+            return Ok(None);
+        };
+        let name = track.name.as_str();
+
+        if !self.allow.is_empty() && !self.allow.iter().any(|pattern| glob_match(pattern, name)) {
+            anyhow::bail!("Track event {name} filtered out (not in track_name_allowlist)");
+        }
+        if let Some(pattern) = self.deny.iter().find(|pattern| glob_match(pattern, name)) {
+            anyhow::bail!(
+                "Track event {name} filtered out (matches {pattern} in track_name_denylist)"
+            );
+        }
+
+        Ok(self
+            .key_prefixes
+            .iter()
+            .find(|(pattern, _)| glob_match(pattern, name))
+            .map(|(_, key_prefix)| Destination {
+                key_prefix: Some(key_prefix.clone()),
+                ..Destination::default()
+            }))
+    }
+}
+
+fn parse_patterns(patterns: &str, key: &str) -> anyhow::Result<Vec<String>> {
+    split_list(patterns)
+        .map(|pattern| {
+            check_pattern(pattern, key)?;
+            Ok(pattern.to_string())
+        })
+        .collect()
+}
+
+pub fn check_pattern(pattern: &str, key: &str) -> anyhow::Result<()> {
+    if pattern.is_empty() {
+        anyhow::bail!("Empty track name pattern in {key}");
+    }
+    if pattern.chars().any(char::is_control) {
+        anyhow::bail!("Invalid track name pattern in {key}: {pattern}");
+    }
+    Ok(())
+}
+
+/// Matches a name against a glob with `*` and `?` wildcards, backtracking on the last `*`.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut last_star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                last_star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match last_star {
+                // let the last star match one more character
+                Some((star_p, star_n)) => {
+                    last_star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn matches_globs() {
+        for (pattern, name, matches) in [
+            ("purchase", "purchase", true),
+            ("purchase", "purchases", false),
+            ("checkout_*", "checkout_started", true),
+            ("checkout_*", "checkout_", true),
+            ("checkout_*", "checkout", false),
+            ("*_test", "signup_test", true),
+            ("*_test", "signup_test_2", false),
+            ("*debug*", "my_debug_event", true),
+            ("a*b*c", "aXXbYYbZZc", true),
+            ("a*b*c", "aXXbYYbZZ", false),
+            ("step_?", "step_1", true),
+            ("step_?", "step_10", false),
+            ("*", "", true),
+            ("?", "", false),
+            ("évén*", "événement", true),
+        ] {
+            assert_eq!(glob_match(pattern, name), matches, "{pattern} {name}");
+        }
+    }
+}